@@ -3,6 +3,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::{Mutex, Semaphore};
 use llmrs::backend::EmbeddingBackend;
+use llmrs::types::{EmbedInput, Truncation};
 use sysinfo::System;
 
 #[tokio::main]
@@ -18,7 +19,10 @@ async fn main() {
     println!("[{}] 正在加载模型...", timestamp());
     let load_start = Instant::now();
     let backend = Arc::new(
-        llmrs::backend::candle::CandleBackend::new("./models/Yuan-embedding-2.0-zh".to_string())
+        llmrs::backend::candle::CandleBackend::new(
+            "./models/Yuan-embedding-2.0-zh".to_string(),
+            llmrs::backend::candle::CandleOptions::default(),
+        )
             .expect("Failed to create backend"),
     );
     println!("[{}] 模型加载完成，耗时: {:.2}s\n", timestamp(), load_start.elapsed().as_secs_f64());
@@ -28,11 +32,11 @@ async fn main() {
     println!("[{}] 模型加载后内存: {:.0} MB\n", timestamp(), mem_after_load);
 
     println!("[{}] 预热中...", timestamp());
-    let _ = backend.embed(vec!["预热文本用于初始化模型状态".to_string()], true, 1).await;
+    let _ = backend.embed(vec![EmbedInput::from("预热文本用于初始化模型状态".to_string())], true, 1, Truncation::Right, None).await;
     println!("[{}] 预热完成\n", timestamp());
 
     let test_text = "这是一段用于性能测试的中文文本内容，长度约为三十个汉字左右。";
-    let test_text_vec = vec![test_text.to_string()];
+    let test_text_vec = vec![EmbedInput::from(test_text.to_string())];
 
     let concurrency_levels = vec![20, 50, 70];
     let test_duration = Duration::from_secs(60);
@@ -117,7 +121,7 @@ async fn main() {
 
                     let handle = tokio::spawn(async move {
                         let req_start = Instant::now();
-                        let result = backend_ref.embed(text, true, 1).await;
+                        let result = backend_ref.embed(text, true, 1, Truncation::Right, None).await;
                         let duration = req_start.elapsed();
                         drop(permit);
 
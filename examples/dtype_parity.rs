@@ -0,0 +1,129 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use llmrs::backend::candle::{CandleBackend, CandleOptions, ModelDType};
+use llmrs::backend::EmbeddingBackend;
+use llmrs::types::{EmbedInput, Truncation};
+use sysinfo::System;
+
+// 用法: cargo run --release --example dtype_parity -- [模型目录] [f16|bf16] [最小余弦相似度阈值]
+//
+// 最小余弦相似度低于阈值（默认 0.999）时以非零状态退出，可用于 CI 检查
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let model_path = args
+        .next()
+        .unwrap_or_else(|| "./models/Yuan-embedding-2.0-zh".to_string());
+    let dtype: ModelDType = args
+        .next()
+        .unwrap_or_else(|| "f16".to_string())
+        .parse()
+        .expect("Invalid dtype");
+    let floor: f32 = args
+        .next()
+        .map(|s| s.parse().expect("Invalid cosine floor"))
+        .unwrap_or(0.999);
+
+    println!("╔════════════════════════════════════════════════════════════╗");
+    println!("║         降精度推理一致性测试 (对比 f32 基准)                ║");
+    println!("╚════════════════════════════════════════════════════════════╝\n");
+    println!("  - 模型目录: {}", model_path);
+    println!("  - 对比精度: {:?}", dtype);
+    println!("  - 余弦阈值: {}\n", floor);
+
+    let texts: Vec<String> = [
+        "这是一段用于性能测试的中文文本内容，长度约为三十个汉字左右。",
+        "今天天气很好，适合出去散步。",
+        "向量检索系统需要把文本转换为稠密向量。",
+        "Rust 是一门注重安全与性能的系统编程语言。",
+        "The quick brown fox jumps over the lazy dog.",
+        "短文本",
+        "大语言模型的嵌入向量可以用于语义搜索、聚类和推荐系统等多种下游任务，\
+         在检索增强生成中也扮演着重要角色。",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+
+    let (baseline, baseline_mem) = embed_with(&model_path, ModelDType::F32, &texts).await;
+    let (reduced, reduced_mem) = embed_with(&model_path, dtype, &texts).await;
+
+    let mut similarities = Vec::with_capacity(texts.len());
+    for (i, (a, b)) in baseline.iter().zip(reduced.iter()).enumerate() {
+        let cos = cosine(a, b);
+        println!("  [{}] cos = {:.6}  drift = {:.2e}", i, cos, 1.0 - cos);
+        similarities.push(cos);
+    }
+
+    let min = similarities.iter().copied().fold(f32::MAX, f32::min);
+    let mean = similarities.iter().sum::<f32>() / similarities.len() as f32;
+
+    println!("\n┌─────────────────────────────────────────────────────────────────────────┐");
+    println!("│  一致性指标                                                              │");
+    println!("├─────────────────────────────────────────────────────────────────────────┤");
+    println!("│  最小余弦相似度:  {:>15.6}                                │", min);
+    println!("│  平均余弦相似度:  {:>15.6}                                │", mean);
+    println!("│  最大偏移:        {:>15.2e}                                │", 1.0 - min);
+    println!("│  f32 模型内存:    {:>15.0} MB                             │", baseline_mem);
+    println!("│  {:?} 模型内存:    {:>15.0} MB                             │", dtype, reduced_mem);
+    println!("└─────────────────────────────────────────────────────────────────────────┘");
+
+    if min < floor {
+        eprintln!("\n最小余弦相似度 {:.6} 低于阈值 {}", min, floor);
+        std::process::exit(1);
+    }
+}
+
+async fn embed_with(model_path: &str, dtype: ModelDType, texts: &[String]) -> (Vec<Vec<f32>>, f64) {
+    println!("[{}] 加载 {:?} 模型...", timestamp(), dtype);
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    let pid = sysinfo::get_current_pid().unwrap();
+    let mem_before = get_process_memory(&sys, pid);
+
+    let load_start = Instant::now();
+    let options = CandleOptions {
+        dtype,
+        ..CandleOptions::default()
+    };
+    let backend = CandleBackend::new(model_path.to_string(), options)
+        .expect("Failed to create backend");
+    sys.refresh_all();
+    let mem = get_process_memory(&sys, pid) - mem_before;
+    println!("[{}] 加载完成，耗时: {:.2}s", timestamp(), load_start.elapsed().as_secs_f64());
+
+    let response = backend
+        .embed(
+            texts.iter().cloned().map(EmbedInput::from).collect(),
+            true,
+            texts.len() as u32,
+            Truncation::Right,
+            None,
+        )
+        .await
+        .expect("Embedding failed");
+    (response.vectors, mem)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm_a * norm_b).max(1e-12)
+}
+
+fn timestamp() -> String {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap();
+    let secs = duration.as_secs();
+    let hours = (secs % 86400) / 3600;
+    let mins = (secs % 3600) / 60;
+    let secs = secs % 60;
+    format!("{:02}:{:02}:{:02}", hours, mins, secs)
+}
+
+fn get_process_memory(sys: &System, pid: sysinfo::Pid) -> f64 {
+    sys.process(pid)
+        .map(|p| p.memory() as f64 / 1024.0 / 1024.0)
+        .unwrap_or(0.0)
+}
@@ -1,30 +1,118 @@
 use async_trait::async_trait;
 use reqwest::StatusCode;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use tokenizers::{Encoding, PostProcessor, Tokenizer, TruncationDirection};
 
-use crate::types::{EmbedRequest, EmbedResponse, InputText};
+use crate::backend::prompts::Prompts;
+use crate::types::{
+    EmbedInput, EmbedRequest, EmbedResponse, InputText, RerankScores, TokenInfo, Tokenized,
+    Truncation,
+};
 
 pub mod candle;
+pub mod prompts;
 
+/// 后端所加载模型的描述信息，用于 /health、/v1/models 等接口展示
+///
+/// proxy 后端无法得知上游模型的信息，各字段为 None。
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ModelInfo {
+    /// GGUF 模型的量化类型，非量化模型为 None
+    pub quantization: Option<String>,
+    /// 输出向量的维度
+    pub dimension: Option<usize>,
+    /// 单条输入允许的最大 token 数（含特殊 token）
+    pub max_seq_len: Option<usize>,
+    pub pooling: Option<candle::Pooling>,
+    pub task: ModelTask,
+}
 
+/// 模型提供的能力：嵌入向量或重排序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelTask {
+    #[default]
+    Embedding,
+    Rerank,
+}
 
 #[async_trait]
 pub trait EmbeddingBackend: Send + Sync {
     async fn embed(
         &self,
-        texts: Vec<String>,
+        inputs: Vec<EmbedInput>,
         normalize_embeddings: bool,
         batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
     ) -> Result<EmbedResponse, BackendError>;
+
+    /// 计算 query 与各文档的相关性分数，只有 cross-encoder 后端支持
+    async fn rerank(
+        &self,
+        _query: String,
+        _documents: Vec<String>,
+        _batch_size: u32,
+    ) -> Result<RerankScores, BackendError> {
+        Err(BackendError::InvalidInput(
+            "this model does not support rerank".to_string(),
+        ))
+    }
+
+    /// 返回每条输入各 token 的隐藏状态（未池化），用于 TEI 的 /embed_all
+    async fn embed_all(
+        &self,
+        _inputs: Vec<EmbedInput>,
+        _batch_size: u32,
+        _truncation: Truncation,
+        _prompt_name: Option<String>,
+    ) -> Result<Vec<Vec<Vec<f32>>>, BackendError> {
+        Err(BackendError::InvalidInput(
+            "this model does not support token embeddings".to_string(),
+        ))
+    }
+
+    /// 用模型的 tokenizer 对文本分词，文本按 embed 的规则加上提示词前缀
+    fn tokenize(
+        &self,
+        _inputs: Vec<String>,
+        _add_special_tokens: bool,
+        _prompt_name: Option<&str>,
+    ) -> Result<Vec<Tokenized>, BackendError> {
+        Err(BackendError::InvalidInput(
+            "this model has no tokenizer configured".to_string(),
+        ))
+    }
+
+    /// 将 token id 序列解码为文本
+    fn detokenize(
+        &self,
+        _inputs: Vec<Vec<u32>>,
+        _skip_special_tokens: bool,
+    ) -> Result<Vec<String>, BackendError> {
+        Err(BackendError::InvalidInput(
+            "this model has no tokenizer configured".to_string(),
+        ))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::default()
+    }
 }
 
 #[derive(Clone)]
 pub struct BackendClient {
     base_url: String,
     client: reqwest::Client,
+    // 配置后用于统计 token 数，否则使用上游返回的 token_counts
+    tokenizer: Option<Arc<Tokenizer>>,
+    // 文本在本地加上提示词前缀后再转发
+    prompts: Arc<Prompts>,
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum BackendError {
     #[error("backend request failed: {0}")]
     Request(String),
@@ -32,27 +120,152 @@ pub enum BackendError {
     Status(StatusCode),
     #[error("backend decode failed: {0}")]
     Decode(String),
+    #[error("model load failed: {0}")]
+    Model(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("inference failed: {0}")]
+    Inference(String),
+    #[error("backend request timed out: {0}")]
+    Timeout(String),
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    /// 队列已满，请求被立即拒绝
+    #[error("queue is full: {depth} requests queued")]
+    QueueFull {
+        depth: usize,
+        capacity: usize,
+        retry_after: u64,
+    },
+    /// 等待入队超时，或排队中的请求被新请求挤出
+    #[error("server overloaded: {reason}")]
+    Overloaded {
+        reason: String,
+        depth: usize,
+        capacity: usize,
+        retry_after: u64,
+    },
 }
 
 impl BackendClient {
-    pub fn new(base_url: String) -> Self {
+    /// timeout 为单次上游请求的超时时间，None 表示不限制
+    pub fn new(
+        base_url: String,
+        tokenizer: Option<Tokenizer>,
+        prompts: Prompts,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
         Self {
             base_url,
-            client: reqwest::Client::new(),
+            client: builder.build().unwrap_or_default(),
+            tokenizer: tokenizer.map(Arc::new),
+            prompts: Arc::new(prompts),
         }
     }
 
     pub async fn embed(
         &self,
-        texts: Vec<String>,
+        inputs: Vec<EmbedInput>,
         normalize_embeddings: bool,
         batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
+    ) -> Result<EmbedResponse, BackendError> {
+        let prefix = self.prompt_prefix(prompt_name.as_deref())?;
+        let inputs: Vec<EmbedInput> = match prefix {
+            Some(prefix) => inputs
+                .into_iter()
+                .map(|input| match input {
+                    EmbedInput::Text(text) => EmbedInput::Text(format!("{}{}", prefix, text)),
+                    tokens => tokens,
+                })
+                .collect(),
+            None => inputs,
+        };
+        let token_counts = self.count_tokens(&inputs);
+        // 配置了 tokenizer 时校验 token id 并解码为文本，否则原样转发给上游
+        let inputs = match &self.tokenizer {
+            Some(tokenizer) => inputs
+                .into_iter()
+                .map(|input| decode_input(tokenizer, input))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => inputs,
+        };
+
+        let mut texts = Vec::new();
+        let mut tokens = Vec::new();
+        let mut is_text = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            match input {
+                EmbedInput::Text(text) => {
+                    texts.push(text);
+                    is_text.push(true);
+                }
+                EmbedInput::Tokens(ids) => {
+                    tokens.push(ids);
+                    is_text.push(false);
+                }
+            }
+        }
+
+        let mut response = if tokens.is_empty() {
+            self.post_embed(InputText::Multiple(texts), normalize_embeddings, batch_size, truncation)
+                .await?
+        } else if texts.is_empty() {
+            self.post_embed(InputText::TokenBatch(tokens), normalize_embeddings, batch_size, truncation)
+                .await?
+        } else {
+            // 文本与 token id 混合时分两次请求，再按原顺序合并
+            let text_response = self
+                .post_embed(InputText::Multiple(texts), normalize_embeddings, batch_size, truncation)
+                .await?;
+            let token_response = self
+                .post_embed(InputText::TokenBatch(tokens), normalize_embeddings, batch_size, truncation)
+                .await?;
+            interleave(&is_text, text_response, token_response)
+        };
+        if let Some(token_counts) = token_counts {
+            response.token_counts = token_counts;
+        }
+        Ok(response)
+    }
+
+    fn require_tokenizer(&self) -> Result<&Tokenizer, BackendError> {
+        self.tokenizer.as_deref().ok_or_else(|| {
+            BackendError::InvalidInput(
+                "tokenization requires TOKENIZER_PATH for the proxy backend".to_string(),
+            )
+        })
+    }
+
+    fn prompt_prefix(&self, prompt_name: Option<&str>) -> Result<Option<&str>, BackendError> {
+        match prompt_name {
+            Some(name) => self.prompts.get(name).map(Some),
+            None => Ok(self.prompts.default_prompt()),
+        }
+    }
+
+    async fn post_embed(
+        &self,
+        texts: InputText,
+        normalize_embeddings: bool,
+        batch_size: u32,
+        truncation: Truncation,
     ) -> Result<EmbedResponse, BackendError> {
         let url = format!("{}/embed", self.base_url.trim_end_matches('/'));
         let payload = EmbedRequest {
-            texts: InputText::Multiple(texts),
+            texts,
+            model: None,
             normalize_embeddings,
             batch_size,
+            truncation: Some(truncation),
+            // 前缀已在本地加上
+            prompt_name: None,
+            input_type: None,
         };
 
         let res = self
@@ -61,7 +274,13 @@ impl BackendClient {
             .json(&payload)
             .send()
             .await
-            .map_err(|e| BackendError::Request(e.to_string()))?;
+            .map_err(|e| {
+                if e.is_timeout() {
+                    BackendError::Timeout(e.to_string())
+                } else {
+                    BackendError::Request(e.to_string())
+                }
+            })?;
 
         if !res.status().is_success() {
             return Err(BackendError::Status(res.status()));
@@ -71,30 +290,259 @@ impl BackendClient {
             .await
             .map_err(|e| BackendError::Decode(e.to_string()))
     }
+
+    // 本地 tokenizer 统计每条输入的 token 数，未配置或编码失败时返回 None
+    fn count_tokens(&self, inputs: &[EmbedInput]) -> Option<Vec<usize>> {
+        let tokenizer = self.tokenizer.as_ref()?;
+        inputs
+            .iter()
+            .map(|input| match input {
+                EmbedInput::Text(text) => tokenizer.encode(text.as_str(), true).ok().map(|e| e.len()),
+                EmbedInput::Tokens(ids) => Some(ids.len()),
+            })
+            .collect()
+    }
+}
+
+// 校验 token id 都在词表内，并解码为文本（去掉特殊 token，由上游重新添加）
+fn decode_input(tokenizer: &Tokenizer, input: EmbedInput) -> Result<EmbedInput, BackendError> {
+    let EmbedInput::Tokens(ids) = input else {
+        return Ok(input);
+    };
+    check_token_ids(tokenizer, &ids)?;
+    tokenizer
+        .decode(&ids, true)
+        .map(EmbedInput::Text)
+        .map_err(|e| BackendError::InvalidInput(format!("failed to decode token ids: {}", e)))
+}
+
+/// 分词并描述每个 token，偏移按字符计算
+///
+/// 前缀与正文一起编码，与嵌入时的分词一致；前缀对应的 token 标记为 prefix，
+/// 正文 token 的偏移相对于原始输入（不含前缀）。
+/// max_seq_len 为模型的最大长度（含特殊 token），用于计算嵌入时会被截断的 token 数；
+/// 不加特殊 token 分词时按嵌入时会补上的特殊 token 计算。
+pub fn tokenize_text(
+    tokenizer: &Tokenizer,
+    prefix: &str,
+    text: &str,
+    add_special_tokens: bool,
+    max_seq_len: Option<usize>,
+) -> Result<Tokenized, BackendError> {
+    let tokenize_error =
+        |e: tokenizers::Error| BackendError::InvalidInput(format!("failed to tokenize input: {}", e));
+    let prefix_chars = prefix.chars().count();
+    let encoding = tokenizer
+        .encode_char_offsets(format!("{}{}", prefix, text), false)
+        .map_err(tokenize_error)?;
+    let (prefix_encoding, encoding) = split_prefix(encoding, prefix_chars);
+    let prefix_tokens = prefix_encoding.len();
+    let encoding = Encoding::merge([prefix_encoding, encoding], false);
+    let encoding = if add_special_tokens {
+        tokenizer
+            .post_process(encoding, None, true)
+            .map_err(tokenize_error)?
+    } else {
+        encoding
+    };
+
+    let chars: Vec<char> = prefix.chars().chain(text.chars()).collect();
+    let mut non_special = 0;
+    let tokens: Vec<TokenInfo> = encoding
+        .get_ids()
+        .iter()
+        .zip(encoding.get_tokens())
+        .zip(encoding.get_offsets())
+        .zip(encoding.get_special_tokens_mask())
+        .map(|(((&id, token), &(start, stop)), &special)| {
+            let special = special == 1;
+            // 特殊 token 插在前缀之前或正文之后，按非特殊 token 的序号区分前缀
+            let in_prefix = !special && non_special < prefix_tokens;
+            if !special {
+                non_special += 1;
+            }
+            let text = if special {
+                token.clone()
+            } else {
+                chars[start.min(chars.len())..stop.min(chars.len())].iter().collect()
+            };
+            let offsets = (!special && !in_prefix).then(|| {
+                (
+                    start.saturating_sub(prefix_chars),
+                    stop.saturating_sub(prefix_chars),
+                )
+            });
+            TokenInfo {
+                id,
+                token: token.clone(),
+                text,
+                special,
+                prefix: in_prefix,
+                offsets,
+            }
+        })
+        .collect();
+
+    let special_tokens = if add_special_tokens {
+        0
+    } else {
+        tokenizer
+            .get_post_processor()
+            .map_or(0, |p| p.added_tokens(false))
+    };
+    let truncated_tokens =
+        max_seq_len.map(|max_len| (tokens.len() + special_tokens).saturating_sub(max_len));
+    Ok(Tokenized {
+        tokens,
+        truncated_tokens,
+    })
+}
+
+/// 按偏移拆分前缀与正文：起点落在前缀内的 token 归入前缀（跨越交界的 token 也算前缀）
+///
+/// 偏移的单位与 prefix_len 一致，字节偏移或字符偏移均可。
+pub fn split_prefix(encoding: Encoding, prefix_len: usize) -> (Encoding, Encoding) {
+    let prefix_tokens = encoding
+        .get_offsets()
+        .iter()
+        .take_while(|(start, _)| *start < prefix_len)
+        .count();
+    let text_tokens = encoding.len() - prefix_tokens;
+    let mut prefix = encoding.clone();
+    prefix.truncate(prefix_tokens, 0, TruncationDirection::Right);
+    prefix.take_overflowing();
+    let mut text = encoding;
+    text.truncate(text_tokens, 0, TruncationDirection::Left);
+    text.take_overflowing();
+    (prefix, text)
+}
+
+/// 校验 token id 都在词表内后解码，空序列解码为空字符串
+pub fn detokenize_ids(
+    tokenizer: &Tokenizer,
+    ids: &[u32],
+    skip_special_tokens: bool,
+) -> Result<String, BackendError> {
+    if ids.is_empty() {
+        return Ok(String::new());
+    }
+    check_token_ids(tokenizer, ids)?;
+    tokenizer
+        .decode(ids, skip_special_tokens)
+        .map_err(|e| BackendError::InvalidInput(format!("failed to decode token ids: {}", e)))
+}
+
+/// 校验 token id 非空且都在 tokenizer 的词表（含 added tokens）内
+pub fn check_token_ids(tokenizer: &Tokenizer, ids: &[u32]) -> Result<(), BackendError> {
+    if ids.is_empty() {
+        return Err(BackendError::InvalidInput(
+            "token id array cannot be empty".to_string(),
+        ));
+    }
+    match ids.iter().find(|&&id| tokenizer.id_to_token(id).is_none()) {
+        Some(id) => Err(BackendError::InvalidInput(format!(
+            "token id {} is out of range for vocabulary size {}",
+            id,
+            tokenizer.get_vocab_size(true)
+        ))),
+        None => Ok(()),
+    }
+}
+
+// 按 is_text 记录的原始位置合并两次请求的结果
+fn interleave(is_text: &[bool], texts: EmbedResponse, tokens: EmbedResponse) -> EmbedResponse {
+    let has_token_counts = texts.token_counts.len() == texts.vectors.len()
+        && tokens.token_counts.len() == tokens.vectors.len();
+    let token_counts = if has_token_counts {
+        merge_by_position(is_text, texts.token_counts, tokens.token_counts)
+    } else {
+        Vec::new()
+    };
+    let has_truncated = texts.truncated.len() == texts.vectors.len()
+        && tokens.truncated.len() == tokens.vectors.len();
+    let truncated = if has_truncated {
+        merge_by_position(is_text, texts.truncated, tokens.truncated)
+    } else {
+        Vec::new()
+    };
+    let vectors = merge_by_position(is_text, texts.vectors, tokens.vectors);
+    EmbedResponse {
+        count: vectors.len(),
+        vector_dim: texts.vector_dim,
+        model_path: texts.model_path,
+        vectors,
+        token_counts,
+        truncated,
+    }
+}
+
+fn merge_by_position<T>(is_text: &[bool], texts: Vec<T>, tokens: Vec<T>) -> Vec<T> {
+    let mut texts = texts.into_iter();
+    let mut tokens = tokens.into_iter();
+    is_text
+        .iter()
+        .filter_map(|&text| if text { texts.next() } else { tokens.next() })
+        .collect()
 }
 
 #[async_trait]
 impl EmbeddingBackend for BackendClient {
     async fn embed(
         &self,
-        texts: Vec<String>,
+        inputs: Vec<EmbedInput>,
         normalize_embeddings: bool,
         batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
     ) -> Result<EmbedResponse, BackendError> {
-        self.embed(texts, normalize_embeddings, batch_size).await
+        self.embed(inputs, normalize_embeddings, batch_size, truncation, prompt_name)
+            .await
+    }
+
+    fn tokenize(
+        &self,
+        inputs: Vec<String>,
+        add_special_tokens: bool,
+        prompt_name: Option<&str>,
+    ) -> Result<Vec<Tokenized>, BackendError> {
+        let tokenizer = self.require_tokenizer()?;
+        let prefix = self.prompt_prefix(prompt_name)?.unwrap_or_default();
+        // 上游模型的最大长度未知，不计算截断
+        inputs
+            .iter()
+            .map(|text| tokenize_text(tokenizer, prefix, text, add_special_tokens, None))
+            .collect()
+    }
+
+    fn detokenize(
+        &self,
+        inputs: Vec<Vec<u32>>,
+        skip_special_tokens: bool,
+    ) -> Result<Vec<String>, BackendError> {
+        let tokenizer = self.require_tokenizer()?;
+        inputs
+            .iter()
+            .map(|ids| detokenize_ids(tokenizer, ids, skip_special_tokens))
+            .collect()
     }
 }
 
 // 后端类型枚举
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Deserialize)]
 pub enum BackendType {
+    #[default]
     Proxy,
     Candle,
+    Gguf,
 }
 
-impl Default for BackendType {
-    fn default() -> Self {
-        Self::Proxy
+impl BackendType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Proxy => "proxy",
+            Self::Candle => "candle",
+            Self::Gguf => "gguf",
+        }
     }
 }
 
@@ -105,6 +553,7 @@ impl std::str::FromStr for BackendType {
         match s.to_lowercase().as_str() {
             "proxy" => Ok(Self::Proxy),
             "candle" => Ok(Self::Candle),
+            "gguf" => Ok(Self::Gguf),
             _ => Err(format!("Invalid backend type: {}", s)),
         }
     }
@@ -1,95 +1,412 @@
 use async_trait::async_trait;
-use candle_core::{Device, Tensor};
-use candle_nn::VarBuilder;
-use candle_transformers::models::bert::{BertModel, Config};
+use candle_core::{DType, Device, Tensor};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
-use tokenizers::{Tokenizer, TruncationDirection};
+use tokenizers::{Encoding, PostProcessor, Tokenizer, TruncationDirection};
 
-use crate::backend::{BackendError, EmbeddingBackend};
-use crate::types::EmbedResponse;
+use crate::backend::prompts::Prompts;
+use crate::backend::{
+    check_token_ids, detokenize_ids, split_prefix, tokenize_text, BackendError, EmbeddingBackend,
+    ModelInfo, ModelTask,
+};
+use crate::types::{EmbedInput, EmbedResponse, Tokenized, Truncation};
+
+mod compute;
+mod cross_encoder;
+mod decoder;
+mod gguf;
+mod model;
+mod model_config;
+mod modules;
+mod pooling;
+mod quantized_bert;
+mod weights;
+
+use compute::ComputePool;
+pub use compute::init_intra_op_threads;
+use gguf::GgufModel;
+use model::EmbeddingModel;
+use model_config::ModelConfig;
+use modules::Pipeline;
+pub use cross_encoder::CrossEncoderBackend;
+pub use pooling::Pooling;
+
+/// 模型权重加载后使用的精度，输出向量始终转换回 f32
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelDType {
+    #[default]
+    F32,
+    F16,
+    Bf16,
+}
+
+impl From<ModelDType> for DType {
+    fn from(dtype: ModelDType) -> Self {
+        match dtype {
+            ModelDType::F32 => DType::F32,
+            ModelDType::F16 => DType::F16,
+            ModelDType::Bf16 => DType::BF16,
+        }
+    }
+}
+
+impl std::str::FromStr for ModelDType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "f32" | "float32" => Ok(Self::F32),
+            "f16" | "float16" => Ok(Self::F16),
+            "bf16" | "bfloat16" => Ok(Self::Bf16),
+            _ => Err(format!("Invalid dtype: {}", s)),
+        }
+    }
+}
+
+/// Candle 后端的可选参数，未设置的项从模型目录自动读取
+#[derive(Debug, Clone, Default)]
+pub struct CandleOptions {
+    pub pooling: Option<Pooling>,
+    pub dtype: ModelDType,
+    /// decoder 嵌入模型的任务指令，设置后输入按 "Instruct: {指令}\nQuery: {文本}" 格式拼接
+    pub instruction: Option<String>,
+    /// 命名提示词，与模型目录 config_sentence_transformers.json 中的 prompts 合并，同名时以此为准
+    pub prompts: Prompts,
+    /// 推理线程池的线程数，即可同时执行的前向计算数（0 按 1 处理）
+    pub compute_threads: usize,
+    /// 每批填充后的最大 token 数（条数 × 批内最长长度），0 表示只按条数分批
+    pub max_batch_tokens: usize,
+    /// 分块处理超长输入时相邻窗口重叠的 token 数
+    pub chunk_overlap: usize,
+}
+
+// encode_inputs 的结果，各字段与输入一一对应
+struct Encoded {
+    embeddings: Vec<Vec<f32>>,
+    token_counts: Vec<usize>,
+    truncated: Vec<bool>,
+}
 
 #[derive(Clone)]
 pub struct CandleBackend {
-    model: Arc<BertModel>,
+    model: Arc<EmbeddingModel>,
     tokenizer: Arc<Tokenizer>,
     device: Device,
     model_path: String,
+    max_seq_len: usize,
+    dimension: usize,
+    pad_token_id: u32,
+    pooling: Pooling,
+    pipeline: Arc<Pipeline>,
+    instruction: Option<String>,
+    prompts: Arc<Prompts>,
+    quantization: Option<String>,
+    pool: Arc<ComputePool>,
+    max_batch_tokens: usize,
+    chunk_overlap: usize,
 }
 
 impl CandleBackend {
-    pub fn new(model_path: String) -> Result<Self, BackendError> {
+    pub fn new(model_path: String, options: CandleOptions) -> Result<Self, BackendError> {
         let device = Device::Cpu;
+        check_dtype_supported(options.dtype, &device)?;
         
-        // 加载 tokenizer
-        let tokenizer = Tokenizer::from_file(format!("{}/tokenizer.json", model_path))
-            .map_err(|e| BackendError::Request(format!("Failed to load tokenizer: {}", e)))?;
-        
-        // 加载模型配置
-        let config = Config {
-            vocab_size: 21128,
-            hidden_size: 1024,
-            num_hidden_layers: 24,
-            num_attention_heads: 16,
-            intermediate_size: 4096,
-            max_position_embeddings: 512,
-            type_vocab_size: 2,
-            ..Config::default()
-        };
+        // 从 config.json 加载模型配置，按 model_type 选择 BERT、XLM-RoBERTa 或 decoder 模型
+        let config = model_config::load_model_config(&model_path)?;
+        
+        // 加载模型权重：分片 / 单文件 safetensors（mmap），或 pytorch_model.bin
+        let vb = weights::load_var_builder(Path::new(&model_path), options.dtype.into(), &device)?;
+        
+        let model = EmbeddingModel::load(&config, vb)?;
+        Self::build(model_path, model, config, options, None, None, device)
+    }
+
+    /// 加载 GGUF 量化模型，model_path 可以是 .gguf 文件或包含它的目录
+    ///
+    /// tokenizer.json 以及 sentence-transformers 的池化配置从同一目录读取。
+    pub fn from_gguf(model_path: String, options: CandleOptions) -> Result<Self, BackendError> {
+        let device = Device::Cpu;
+        let (gguf_path, model_dir) = gguf::resolve_gguf_path(&model_path)?;
+        let gguf = GgufModel::load(&gguf_path, &device)?;
+        let model = EmbeddingModel::load_quantized(&gguf.config, gguf.weights)?;
+        Self::build(
+            model_dir,
+            model,
+            gguf.config,
+            options,
+            gguf.pooling,
+            Some(gguf.quantization),
+            device,
+        )
+    }
+
+    fn build(
+        model_path: String,
+        model: EmbeddingModel,
+        config: ModelConfig,
+        options: CandleOptions,
+        pooling_hint: Option<Pooling>,
+        quantization: Option<String>,
+        device: Device,
+    ) -> Result<Self, BackendError> {
+        let mut tokenizer = Tokenizer::from_file(format!("{}/tokenizer.json", model_path))
+            .map_err(|e| BackendError::Model(format!("Failed to load tokenizer: {}", e)))?;
+        // 超长输入按请求的截断方式处理，不使用 tokenizer.json 自带的截断和填充
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| BackendError::Model(format!("Failed to disable truncation: {}", e)))?;
+        tokenizer.with_padding(None);
+
+        // sentence-transformers 的 modules.json：Pooling 目录以及之后的 Dense / Normalize
+        let pipeline = Pipeline::from_model_dir(&model_path, &device)?;
         
-        // 加载模型权重
-        use candle_core::safetensors::load;
-        let model_file = format!("{}/model.safetensors", model_path);
-        let weights = load(model_file.as_str(), &device)
-            .map_err(|e| BackendError::Request(format!("Failed to load weights: {}", e)))?;
-        let vb = VarBuilder::from_tensors(weights, candle_core::DType::F32, &device);
+        let mut prompts = Prompts::from_model_dir(&model_path)?.merge(options.prompts);
+        // 指令同时作为 query 提示词，input_type="query" 与不指定提示词时的编码一致
+        if let Some(instruction) = &options.instruction {
+            prompts.insert_missing("query", instruction_prefix(instruction));
+        }
         
-        let model = BertModel::load(vb, &config)
-            .map_err(|e| BackendError::Request(format!("Failed to load model: {}", e)))?;
+        // 池化策略：配置优先，其次是 sentence-transformers 的池化配置和 GGUF 元数据，最后按模型结构取默认值
+        let pooling = match options.pooling {
+            Some(pooling) => pooling,
+            None => Pooling::from_model_dir(&model_path, &pipeline.pooling_dir)?
+                .or(pooling_hint)
+                .unwrap_or_else(|| config.default_pooling()),
+        };
         
         Ok(Self {
             model: Arc::new(model),
             tokenizer: Arc::new(tokenizer),
             device,
             model_path,
+            max_seq_len: config.max_seq_len(),
+            dimension: pipeline.output_dim(config.hidden_size()),
+            pad_token_id: config.pad_token_id(),
+            pooling,
+            pipeline: Arc::new(pipeline),
+            instruction: options.instruction,
+            prompts: Arc::new(prompts),
+            quantization,
+            pool: Arc::new(ComputePool::new(options.compute_threads)?),
+            max_batch_tokens: options.max_batch_tokens,
+            chunk_overlap: options.chunk_overlap,
         })
     }
     
-    fn encode_texts(&self, texts: &[String], batch_size: u32) -> Result<Vec<Vec<f32>>, BackendError> {
-        let batch_size = batch_size as usize;
-        let mut all_embeddings = Vec::new();
-        
-        // 批量处理
-        for batch in texts.chunks(batch_size) {
-            let batch_embeddings = self.process_batch(batch)?;
-            all_embeddings.extend(batch_embeddings);
+    /// 返回每条输入的向量、（含特殊 token 的）实际计算 token 数，以及是否被截断
+    ///
+    /// 分块模式下一条输入对应多个窗口，窗口向量在归一化前合并。
+    fn encode_inputs(
+        &self,
+        inputs: &[EmbedInput],
+        batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<&str>,
+    ) -> Result<Encoded, BackendError> {
+        let prefix = self.prompt_prefix(prompt_name)?;
+        let mut encodings = Vec::new();
+        let mut owners = Vec::new();
+        let mut truncated = Vec::with_capacity(inputs.len());
+        for (i, input) in inputs.iter().enumerate() {
+            let (windows, was_truncated) = self.tokenize(i, input, prefix.as_deref(), truncation)?;
+            owners.extend(std::iter::repeat_n(i, windows.len()));
+            encodings.extend(windows);
+            truncated.push(was_truncated);
         }
         
-        Ok(all_embeddings)
+        // 按 token 长度排序后分批，长度相近的文本放在一起，减少填充
+        let mut order: Vec<usize> = (0..encodings.len()).collect();
+        order.sort_by_key(|&i| encodings[i].len());
+        
+        let mut window_embeddings: Vec<Vec<f32>> = vec![Vec::new(); encodings.len()];
+        for batch in plan_batches(&order, &encodings, batch_size as usize, self.max_batch_tokens) {
+            let batch_encodings: Vec<&Encoding> = batch.iter().map(|&i| &encodings[i]).collect();
+            let batch_embeddings = self.process_batch(&batch_encodings).map_err(|e| {
+                // 标明失败批次包含哪些输入
+                let mut batch_inputs: Vec<usize> = batch.iter().map(|&i| owners[i]).collect();
+                batch_inputs.sort_unstable();
+                batch_inputs.dedup();
+                match e {
+                    BackendError::Inference(msg) => {
+                        BackendError::Inference(format!("inputs {:?}: {}", batch_inputs, msg))
+                    }
+                    e => e,
+                }
+            })?;
+            // 按原始顺序放回
+            for (i, embedding) in batch.iter().zip(batch_embeddings) {
+                window_embeddings[*i] = embedding;
+            }
+        }
+        
+        // 按所属输入合并窗口，未分块的输入只有一个窗口
+        let mut windows: Vec<Vec<(Vec<f32>, usize)>> = vec![Vec::new(); inputs.len()];
+        for ((owner, embedding), encoding) in owners.into_iter().zip(window_embeddings).zip(&encodings) {
+            windows[owner].push((embedding, encoding.len()));
+        }
+        let token_counts = windows
+            .iter()
+            .map(|w| w.iter().map(|(_, len)| len).sum())
+            .collect();
+        let embeddings = windows
+            .into_iter()
+            .map(|w| combine_windows(w, truncation))
+            .collect();
+        
+        Ok(Encoded {
+            embeddings,
+            token_counts,
+            truncated,
+        })
+    }
+    
+    /// 文本输入的前缀：请求指定的提示词优先，其次是配置的指令，最后是默认提示词
+    ///
+    /// 指令只作用于查询，请求以 document / passage 提示词编码文档时不加指令。
+    fn prompt_prefix(&self, prompt_name: Option<&str>) -> Result<Option<String>, BackendError> {
+        if let Some(name) = prompt_name {
+            let prompt = self.prompts.get(name)?;
+            return Ok((!prompt.is_empty()).then(|| prompt.to_string()));
+        }
+        Ok(match &self.instruction {
+            Some(instruction) => Some(instruction_prefix(instruction)),
+            None => self.prompts.default_prompt().map(str::to_string),
+        })
     }
     
-    fn process_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, BackendError> {
-        let tokenizer = self.tokenizer.clone();
+    /// 将一条输入编码为一个或多个窗口，并返回是否丢弃了 token
+    ///
+    /// 文本加上前缀后先不加特殊 token 编码，再拆出前缀对应的 token，只对正文截断或切分，
+    /// 之后给每个窗口补回前缀和特殊 token，保证每个窗口都带有完整的指令或提示词，
+    /// 并以 [CLS] / [SEP] 等标记开头结尾。预分词输入按原样切分，不加前缀。
+    fn tokenize(
+        &self,
+        index: usize,
+        input: &EmbedInput,
+        prefix: Option<&str>,
+        truncation: Truncation,
+    ) -> Result<(Vec<Encoding>, bool), BackendError> {
+        let (prefix, mut encoding, special_tokens) = match input {
+            EmbedInput::Text(text) => {
+                let text_str = match prefix {
+                    Some(prefix) => format!("{}{}", prefix, text),
+                    None => text.clone(),
+                };
+                let encoding = self.tokenizer.encode(text_str, false).map_err(|e| {
+                    BackendError::InvalidInput(format!("failed to tokenize input {}: {}", index, e))
+                })?;
+                let special_tokens = self
+                    .tokenizer
+                    .get_post_processor()
+                    .map_or(0, |p| p.added_tokens(false));
+                // 前缀与正文一起编码，保持两者交界处的分词与整体编码一致
+                let (prefix, encoding) = match prefix {
+                    Some(prefix) => {
+                        let (prefix, encoding) = split_prefix(encoding, prefix.len());
+                        (Some(prefix), encoding)
+                    }
+                    None => (None, encoding),
+                };
+                (prefix, encoding, special_tokens)
+            }
+            EmbedInput::Tokens(ids) => (None, self.encoding_from_ids(ids)?, 0),
+        };
+        
+        let prefix_tokens = prefix.as_ref().map_or(0, Encoding::len);
+        let max_len = self
+            .max_seq_len
+            .saturating_sub(special_tokens + prefix_tokens)
+            .max(1);
+        let overflow = encoding.len() > max_len;
+        let windows = if !overflow {
+            vec![encoding]
+        } else {
+            match truncation {
+                Truncation::Error => {
+                    return Err(BackendError::InvalidInput(format!(
+                        "input {} has {} tokens, exceeding the maximum of {}",
+                        index,
+                        encoding.len() + prefix_tokens + special_tokens,
+                        self.max_seq_len
+                    )))
+                }
+                Truncation::Left | Truncation::Right => {
+                    let direction = if truncation == Truncation::Left {
+                        TruncationDirection::Left
+                    } else {
+                        TruncationDirection::Right
+                    };
+                    encoding.truncate(max_len, 0, direction);
+                    encoding.take_overflowing();
+                    vec![encoding]
+                }
+                Truncation::ChunkMean | Truncation::ChunkWeightedMean => {
+                    // 重叠必须小于窗口长度，否则窗口无法前进
+                    let stride = self.chunk_overlap.min(max_len - 1);
+                    encoding.truncate(max_len, stride, TruncationDirection::Right);
+                    let rest = encoding.take_overflowing();
+                    std::iter::once(encoding).chain(rest).collect()
+                }
+            }
+        };
+        
+        let windows = match input {
+            EmbedInput::Text(_) => windows
+                .into_iter()
+                .map(|window| {
+                    let window = match &prefix {
+                        Some(prefix) => Encoding::merge([prefix.clone(), window], false),
+                        None => window,
+                    };
+                    self.tokenizer.post_process(window, None, true).map_err(|e| {
+                        BackendError::InvalidInput(format!("failed to tokenize input {}: {}", index, e))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            EmbedInput::Tokens(_) => windows,
+        };
+        let truncated = overflow && matches!(truncation, Truncation::Left | Truncation::Right);
+        Ok((windows, truncated))
+    }
+    
+    // 预分词输入跳过 tokenizer，按原样构造 Encoding（不添加特殊 token、不拼接指令）
+    fn encoding_from_ids(&self, ids: &[u32]) -> Result<Encoding, BackendError> {
+        check_token_ids(&self.tokenizer, ids)?;
+        let len = ids.len();
+        let tokens = ids
+            .iter()
+            .map(|&id| self.tokenizer.id_to_token(id).unwrap_or_default())
+            .collect();
+        Ok(Encoding::new(
+            ids.to_vec(),
+            vec![0; len],
+            tokens,
+            vec![None; len],
+            vec![(0, 0); len],
+            vec![0; len],
+            vec![1; len],
+            Vec::new(),
+            HashMap::new(),
+        ))
+    }
+    
+    /// 前向计算，返回 (batch, seq, hidden) 的隐藏状态和对应的 attention mask
+    fn forward_batch(&self, tokenized: &[&Encoding]) -> Result<(Tensor, Tensor), BackendError> {
         let model = self.model.clone();
         let device = &self.device;
         
-        // 对文本进行 tokenize
-        let tokenized = texts.iter().map(|text| {
-            let text_str = text.as_str();
-            let mut encoding = tokenizer.encode(text_str, true).unwrap();
-            encoding.truncate(512, 0, TruncationDirection::Right); // 限制长度
-            encoding
-        }).collect::<Vec<_>>();
-        
         // 准备输入张量
         let max_len = tokenized.iter().map(|e| e.len()).max().unwrap_or(1);
         let mut input_ids = Vec::new();
         let mut attention_mask = Vec::new();
-        for encoding in &tokenized {
+        for encoding in tokenized {
             let mut ids = encoding.get_ids().to_vec();
             let mut mask = encoding.get_attention_mask().to_vec();
             // 填充到最大长度
             while ids.len() < max_len {
-                ids.push(0); // [PAD] token
+                ids.push(self.pad_token_id); // [PAD] token
                 mask.push(0); // 注意力掩码
             }
             input_ids.push(ids);
@@ -106,29 +423,79 @@ impl CandleBackend {
         let attention_mask_flat: Vec<i64> = attention_mask_i64.into_iter().flatten().collect();
         
         let input_ids = Tensor::from_vec(input_ids_flat, (batch_size, max_len), device)
-            .map_err(|e| BackendError::Request(format!("Failed to create input tensor: {}", e)))?;
+            .map_err(|e| BackendError::Inference(format!("Failed to create input tensor: {}", e)))?;
         let attention_mask = Tensor::from_vec(attention_mask_flat, (batch_size, max_len), device)
-            .map_err(|e| BackendError::Request(format!("Failed to create attention mask tensor: {}", e)))?;
+            .map_err(|e| BackendError::Inference(format!("Failed to create attention mask tensor: {}", e)))?;
         
         // 前向推理
-        let output = model.forward(&input_ids, &attention_mask, None)
-            .map_err(|e| BackendError::Request(format!("Model forward failed: {}", e)))?;
-        
-        // 获取 [CLS] 标记的输出作为 embedding
-        // 使用index_select方法获取第一个 token (CLS) 的输出
-        let cls_index = Tensor::from_vec(vec![0i64], (1,), device)
-            .map_err(|e| BackendError::Request(format!("Failed to create CLS index: {}", e)))?;
-        let embeddings = output
-            .index_select(&cls_index, 1)
-            .map_err(|e| BackendError::Request(format!("Failed to select CLS token: {}", e)))?
-            .squeeze(1)
-            .map_err(|e| BackendError::Request(format!("Failed to squeeze tensor: {}", e)))?
+        let output = model.forward(&input_ids, &attention_mask)
+            .map_err(|e| BackendError::Inference(format!("Model forward failed: {}", e)))?;
+        Ok((output, attention_mask))
+    }
+    
+    fn process_batch(&self, tokenized: &[&Encoding]) -> Result<Vec<Vec<f32>>, BackendError> {
+        let (output, attention_mask) = self.forward_batch(tokenized)?;
+        
+        // 按配置的策略池化，填充位置由 attention mask 排除
+        let pooled = self
+            .pooling
+            .pool(&output, &attention_mask)
+            .map_err(|e| BackendError::Inference(format!("Failed to pool embeddings: {}", e)))?;
+        
+        // 依次执行 modules.json 中 Pooling 之后的模块
+        let embeddings = self
+            .pipeline
+            .forward(&pooled)
+            .map_err(|e| BackendError::Inference(format!("Failed to apply modules: {}", e)))?
             .to_vec2()
-            .map_err(|e| BackendError::Request(format!("Failed to extract embeddings: {}", e)))?;
+            .map_err(|e| BackendError::Inference(format!("Failed to extract embeddings: {}", e)))?;
         
         Ok(embeddings)
     }
     
+    /// 返回每条输入各 token 的隐藏状态，填充位置已去除；不支持分块
+    fn encode_tokens(
+        &self,
+        inputs: &[EmbedInput],
+        batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<&str>,
+    ) -> Result<Vec<Vec<Vec<f32>>>, BackendError> {
+        if matches!(truncation, Truncation::ChunkMean | Truncation::ChunkWeightedMean) {
+            return Err(BackendError::InvalidInput(
+                "chunked truncation is not supported for token embeddings".to_string(),
+            ));
+        }
+        let prefix = self.prompt_prefix(prompt_name)?;
+        let encodings = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let (mut windows, _) = self.tokenize(i, input, prefix.as_deref(), truncation)?;
+                Ok(windows.remove(0))
+            })
+            .collect::<Result<Vec<Encoding>, BackendError>>()?;
+        
+        let mut order: Vec<usize> = (0..encodings.len()).collect();
+        order.sort_by_key(|&i| encodings[i].len());
+        
+        let mut token_embeddings = vec![Vec::new(); encodings.len()];
+        for batch in plan_batches(&order, &encodings, batch_size as usize, self.max_batch_tokens) {
+            let batch_encodings: Vec<&Encoding> = batch.iter().map(|&i| &encodings[i]).collect();
+            let (output, _) = self.forward_batch(&batch_encodings)?;
+            // 填充在序列末尾，取每条输入的前 len 个位置
+            for (row, &i) in batch.iter().enumerate() {
+                token_embeddings[i] = output
+                    .get(row)
+                    .and_then(|hidden| hidden.narrow(0, 0, encodings[i].len()))
+                    .and_then(|hidden| hidden.to_dtype(DType::F32))
+                    .and_then(|hidden| hidden.to_vec2())
+                    .map_err(|e| BackendError::Inference(format!("Failed to extract token embeddings: {}", e)))?;
+            }
+        }
+        Ok(token_embeddings)
+    }
+    
     fn normalize(&self, embeddings: &mut Vec<Vec<f32>>) {
         for embedding in embeddings {
             let norm = embedding.iter().map(|&x| x * x).sum::<f32>().sqrt();
@@ -141,20 +508,111 @@ impl CandleBackend {
     }
 }
 
+// decoder 嵌入模型的指令前缀
+fn instruction_prefix(instruction: &str) -> String {
+    format!("Instruct: {}\nQuery: ", instruction)
+}
+
+/// 将按长度升序排列的下标划分为批次
+///
+/// 每批填充后的 token 数（条数 × 批内最大长度）不超过 max_batch_tokens（0 表示不限制），
+/// 条数不超过 batch_size；单条超出预算的文本独占一批。
+fn plan_batches(
+    order: &[usize],
+    encodings: &[Encoding],
+    batch_size: usize,
+    max_batch_tokens: usize,
+) -> Vec<Vec<usize>> {
+    let batch_size = batch_size.max(1);
+    let mut batches = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    for &i in order {
+        // 升序排列，新加入的文本就是批内最长的
+        let padded_tokens = (current.len() + 1) * encodings[i].len().max(1);
+        let over_budget = max_batch_tokens > 0 && padded_tokens > max_batch_tokens;
+        if !current.is_empty() && (current.len() >= batch_size || over_budget) {
+            batches.push(std::mem::take(&mut current));
+        }
+        current.push(i);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+// 合并同一输入各窗口的向量：ChunkWeightedMean 按窗口 token 数加权，其余取平均
+fn combine_windows(windows: Vec<(Vec<f32>, usize)>, truncation: Truncation) -> Vec<f32> {
+    if windows.len() == 1 {
+        return windows.into_iter().next().map(|(embedding, _)| embedding).unwrap_or_default();
+    }
+    let dim = windows.first().map_or(0, |(embedding, _)| embedding.len());
+    let mut combined = vec![0f32; dim];
+    let mut total_weight = 0f32;
+    for (embedding, len) in &windows {
+        let weight = match truncation {
+            Truncation::ChunkWeightedMean => *len as f32,
+            _ => 1.0,
+        };
+        for (c, x) in combined.iter_mut().zip(embedding) {
+            *c += x * weight;
+        }
+        total_weight += weight;
+    }
+    if total_weight > 0.0 {
+        combined.iter_mut().for_each(|c| *c /= total_weight);
+    }
+    combined
+}
+
+/// 模型目录中的 config.json 是否描述了 cross-encoder（*ForSequenceClassification）
+pub fn is_cross_encoder(model_path: &str) -> bool {
+    model_config::read_config_json(model_path)
+        .map(|raw| model_config::sequence_classification_labels(&raw).is_some())
+        .unwrap_or(false)
+}
+
+// candle 的 CPU 后端并非所有精度都实现了 matmul（例如 bf16），加载前先探测
+fn check_dtype_supported(dtype: ModelDType, device: &Device) -> Result<(), BackendError> {
+    let probe = Tensor::zeros((1, 1), dtype.into(), device)
+        .and_then(|t| t.matmul(&t));
+    probe.map(|_| ()).map_err(|_| {
+        BackendError::Model(format!(
+            "dtype {:?} is not supported for matmul on {:?}",
+            dtype, device
+        ))
+    })
+}
+
 #[async_trait]
 impl EmbeddingBackend for CandleBackend {
     async fn embed(
         &self,
-        texts: Vec<String>,
+        inputs: Vec<EmbedInput>,
         normalize_embeddings: bool,
         batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
     ) -> Result<EmbedResponse, BackendError> {
-        let mut embeddings = self.encode_texts(&texts, batch_size)?;
-        
-        // 归一化向量
-        if normalize_embeddings {
-            self.normalize(&mut embeddings);
-        }
+        // 前向计算在推理线程池中执行，不占用 tokio 工作线程
+        let backend = self.clone();
+        let count = inputs.len();
+        let Encoded {
+            embeddings,
+            token_counts,
+            truncated,
+        } = self
+            .pool
+            .run(move || {
+                let mut encoded = backend.encode_inputs(&inputs, batch_size, truncation, prompt_name.as_deref())?;
+                
+                // 归一化向量
+                if normalize_embeddings {
+                    backend.normalize(&mut encoded.embeddings);
+                }
+                Ok(encoded)
+            })
+            .await?;
         
         let vector_dim = if embeddings.is_empty() {
             0
@@ -164,9 +622,149 @@ impl EmbeddingBackend for CandleBackend {
         
         Ok(EmbedResponse {
             vectors: embeddings,
-            count: texts.len(),
+            count,
             vector_dim,
             model_path: self.model_path.clone(),
+            token_counts,
+            truncated,
         })
     }
+
+    async fn embed_all(
+        &self,
+        inputs: Vec<EmbedInput>,
+        batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
+    ) -> Result<Vec<Vec<Vec<f32>>>, BackendError> {
+        let backend = self.clone();
+        self.pool
+            .run(move || backend.encode_tokens(&inputs, batch_size, truncation, prompt_name.as_deref()))
+            .await
+    }
+
+    fn tokenize(
+        &self,
+        inputs: Vec<String>,
+        add_special_tokens: bool,
+        prompt_name: Option<&str>,
+    ) -> Result<Vec<Tokenized>, BackendError> {
+        let prefix = self.prompt_prefix(prompt_name)?.unwrap_or_default();
+        inputs
+            .iter()
+            .map(|text| {
+                tokenize_text(
+                    &self.tokenizer,
+                    &prefix,
+                    text,
+                    add_special_tokens,
+                    Some(self.max_seq_len),
+                )
+            })
+            .collect()
+    }
+
+    fn detokenize(
+        &self,
+        inputs: Vec<Vec<u32>>,
+        skip_special_tokens: bool,
+    ) -> Result<Vec<String>, BackendError> {
+        inputs
+            .iter()
+            .map(|ids| detokenize_ids(&self.tokenizer, ids, skip_special_tokens))
+            .collect()
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            quantization: self.quantization.clone(),
+            dimension: Some(self.dimension),
+            max_seq_len: Some(self.max_seq_len),
+            pooling: Some(self.pooling),
+            task: ModelTask::Embedding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按偏移构造 Encoding，id 依次为 0, 1, 2 ...
+    fn encoding(offsets: &[(usize, usize)]) -> Encoding {
+        let len = offsets.len();
+        Encoding::new(
+            (0..len as u32).collect(),
+            vec![0; len],
+            vec![String::new(); len],
+            vec![None; len],
+            offsets.to_vec(),
+            vec![0; len],
+            vec![1; len],
+            Vec::new(),
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn split_prefix_keeps_a_token_crossing_the_boundary_in_the_prefix() {
+        // 前缀 "query: " 为 7 字节，第二个 token 覆盖 [6, 9)，跨越交界
+        let (prefix, text) = split_prefix(encoding(&[(0, 6), (6, 9), (9, 12), (13, 16)]), 7);
+        assert_eq!(prefix.get_ids(), &[0, 1]);
+        assert_eq!(text.get_ids(), &[2, 3]);
+        assert_eq!(text.get_offsets(), &[(9, 12), (13, 16)]);
+    }
+
+    #[test]
+    fn split_prefix_without_prefix_keeps_every_token_in_the_text() {
+        let (prefix, text) = split_prefix(encoding(&[(0, 5), (6, 11)]), 0);
+        assert!(prefix.is_empty());
+        assert_eq!(text.get_ids(), &[0, 1]);
+    }
+
+    // 各输入的 token 数，返回按长度升序排列的 Encoding
+    fn encodings(lens: &[usize]) -> Vec<Encoding> {
+        lens.iter().map(|&len| encoding(&vec![(0, 0); len])).collect()
+    }
+
+    #[test]
+    fn plan_batches_enforces_token_budget_and_batch_size() {
+        let encodings = encodings(&[2, 2, 2, 4, 4, 4]);
+        let order: Vec<usize> = (0..encodings.len()).collect();
+        // 预算 8：三条长度 2 的可以放在一起，但 batch_size 为 2
+        assert_eq!(
+            plan_batches(&order, &encodings, 2, 8),
+            vec![vec![0, 1], vec![2, 3], vec![4, 5]]
+        );
+        // batch_size 足够大时由预算限制：[2, 2, 2] 为 6，加入长度 4 后为 16
+        assert_eq!(
+            plan_batches(&order, &encodings, 16, 8),
+            vec![vec![0, 1, 2], vec![3, 4], vec![5]]
+        );
+    }
+
+    #[test]
+    fn plan_batches_puts_an_input_over_budget_in_its_own_batch() {
+        let encodings = encodings(&[1, 1, 10, 10]);
+        let order: Vec<usize> = (0..encodings.len()).collect();
+        assert_eq!(
+            plan_batches(&order, &encodings, 16, 4),
+            vec![vec![0, 1], vec![2], vec![3]]
+        );
+    }
+
+    #[test]
+    fn combine_windows_averages_chunks() {
+        let windows = vec![(vec![1.0, 2.0], 4), (vec![3.0, 6.0], 2)];
+        assert_eq!(combine_windows(windows, Truncation::ChunkMean), vec![2.0, 4.0]);
+    }
+
+    #[test]
+    fn combine_windows_weights_chunks_by_token_count() {
+        let windows = vec![(vec![1.0, 2.0], 3), (vec![5.0, 6.0], 1)];
+        assert_eq!(
+            combine_windows(windows, Truncation::ChunkWeightedMean),
+            vec![2.0, 3.0]
+        );
+    }
 }
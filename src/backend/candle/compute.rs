@@ -0,0 +1,105 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use tokio::sync::oneshot;
+use tracing::{error, warn};
+
+use crate::backend::BackendError;
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// 专用于模型推理的线程池
+///
+/// 前向计算是长时间占用 CPU 的同步操作，放在 tokio 的工作线程上会阻塞其他请求（包括 /health）。
+/// 推理任务提交到这里的独立线程执行，调用方只异步等待结果。
+pub struct ComputePool {
+    sender: mpsc::Sender<Task>,
+}
+
+impl ComputePool {
+    pub fn new(threads: usize) -> Result<Self, BackendError> {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..threads.max(1) {
+            let rx = receiver.clone();
+            thread::Builder::new()
+                .name(format!("candle-compute-{}", i))
+                .spawn(move || loop {
+                    let task = {
+                        let guard = rx.lock().unwrap_or_else(|e| e.into_inner());
+                        guard.recv()
+                    };
+                    // 线程池被释放后发送端关闭，线程随之退出
+                    let Ok(task) = task else { break };
+                    task();
+                })
+                .map_err(|e| BackendError::Model(format!("Failed to spawn compute thread: {}", e)))?;
+        }
+
+        Ok(Self { sender })
+    }
+
+    /// 在计算线程上执行 f 并等待其结果
+    pub async fn run<F, T>(&self, f: F) -> Result<T, BackendError>
+    where
+        F: FnOnce() -> Result<T, BackendError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let task: Task = Box::new(move || {
+            // 推理代码 panic 时转为错误返回，计算线程继续处理后续任务
+            let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+                Err(BackendError::Inference(format!(
+                    "compute task panicked: {}",
+                    panic_message(payload.as_ref())
+                )))
+            });
+            if tx.send(result).is_err() {
+                error!("compute result receiver dropped");
+            }
+        });
+        self.sender
+            .send(task)
+            .map_err(|_| BackendError::Unavailable("compute pool is shut down".to_string()))?;
+
+        rx.await
+            .map_err(|_| BackendError::Request("compute task dropped".to_string()))?
+    }
+}
+
+/// 取出 panic 携带的消息，非字符串负载返回占位文本
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// 设置单次前向计算内部（矩阵乘等算子）使用的线程数，0 表示使用全部 CPU 核
+///
+/// candle 的 CPU 算子使用 rayon 的全局线程池，该设置对整个进程生效，所有模型共享。
+/// 全局线程池在首次使用时创建，之后无法再修改，因此必须在加载任何模型之前调用一次。
+///
+/// 矩阵乘的任务数另由 candle 的 get_num_threads() 决定（RAYON_NUM_THREADS 或 CPU 核数），
+/// 线程池只限制同时运行的线程数；两者不一致时任务会在较少的线程上排队，因此给出提示。
+pub fn init_intra_op_threads(threads: usize) -> Result<(), BackendError> {
+    if threads == 0 {
+        return Ok(());
+    }
+    let gemm_threads = candle_core::utils::get_num_threads();
+    if gemm_threads != threads {
+        warn!(
+            "candle splits matmul into {} tasks (RAYON_NUM_THREADS or the CPU count) but INTRA_OP_THREADS is {}; set RAYON_NUM_THREADS={} to match",
+            gemm_threads, threads, threads
+        );
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| BackendError::Model(format!("Failed to set intra-op threads: {}", e)))
+}
@@ -0,0 +1,332 @@
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor, D};
+use candle_nn::{linear, Linear, Module, VarBuilder};
+use std::path::Path;
+use std::sync::Arc;
+use tokenizers::{
+    Encoding, Tokenizer, TruncationDirection, TruncationParams, TruncationStrategy,
+};
+
+use super::compute::ComputePool;
+use super::model::EmbeddingModel;
+use super::model_config::{self, ModelConfig};
+use super::{check_dtype_supported, weights, CandleOptions};
+use crate::backend::{
+    detokenize_ids, tokenize_text, BackendError, EmbeddingBackend, ModelInfo, ModelTask,
+};
+use crate::types::{EmbedInput, EmbedResponse, RerankScores, Tokenized, Truncation};
+
+/// 句对打分模型：BertForSequenceClassification 或 XLMRobertaForSequenceClassification
+///
+/// 两者的分类头结构相同（[CLS] 向量 → dense → tanh → 输出层），只是权重名不同：
+/// BERT 为 bert.pooler.dense 与 classifier，XLM-R 为 classifier.dense 与 classifier.out_proj。
+pub struct CrossEncoderModel {
+    encoder: EmbeddingModel,
+    dense: Linear,
+    out_proj: Linear,
+}
+
+impl CrossEncoderModel {
+    pub fn load(config: &ModelConfig, num_labels: usize, vb: VarBuilder) -> Result<Self, BackendError> {
+        let head = match config {
+            ModelConfig::Bert(config) => linear(config.hidden_size, config.hidden_size, vb.pp("bert.pooler.dense"))
+                .and_then(|dense| {
+                    let out_proj = linear(config.hidden_size, num_labels, vb.pp("classifier"))?;
+                    Ok((dense, out_proj))
+                }),
+            ModelConfig::XlmRoberta(config) => linear(config.hidden_size, config.hidden_size, vb.pp("classifier.dense"))
+                .and_then(|dense| {
+                    let out_proj = linear(config.hidden_size, num_labels, vb.pp("classifier.out_proj"))?;
+                    Ok((dense, out_proj))
+                }),
+            ModelConfig::Decoder(_) => {
+                return Err(BackendError::Model(
+                    "Cross-encoder models must be BERT or XLM-RoBERTa".to_string(),
+                ))
+            }
+        };
+        let (dense, out_proj) = head
+            .map_err(|e| BackendError::Model(format!("Failed to load classifier head: {}", e)))?;
+        let encoder = EmbeddingModel::load(config, vb)?;
+        Ok(Self {
+            encoder,
+            dense,
+            out_proj,
+        })
+    }
+
+    /// 返回 (batch, num_labels) 的 logits
+    pub fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: &Tensor,
+    ) -> candle_core::Result<Tensor> {
+        let hidden_states = self
+            .encoder
+            .forward_with_token_types(input_ids, token_type_ids, attention_mask)?;
+        let cls = hidden_states.narrow(1, 0, 1)?.squeeze(1)?;
+        let dense = self.dense.forward(&cls)?.tanh()?;
+        self.out_proj.forward(&dense)?.to_dtype(DType::F32)
+    }
+}
+
+/// 基于 cross-encoder 的重排序后端，只支持 rerank
+#[derive(Clone)]
+pub struct CrossEncoderBackend {
+    model: Arc<CrossEncoderModel>,
+    /// 句对编码使用，超长时截断
+    tokenizer: Arc<Tokenizer>,
+    /// 单段文本分词使用，不截断
+    plain_tokenizer: Arc<Tokenizer>,
+    device: Device,
+    model_path: String,
+    max_seq_len: usize,
+    pad_token_id: u32,
+    pool: Arc<ComputePool>,
+}
+
+impl CrossEncoderBackend {
+    pub fn new(model_path: String, options: CandleOptions) -> Result<Self, BackendError> {
+        let device = Device::Cpu;
+        check_dtype_supported(options.dtype, &device)?;
+
+        let raw = model_config::read_config_json(&model_path)?;
+        let num_labels = model_config::sequence_classification_labels(&raw).ok_or_else(|| {
+            BackendError::Model(format!(
+                "{} is not a sequence classification (cross-encoder) model",
+                model_path
+            ))
+        })?;
+        let config = model_config::load_model_config(&model_path)?;
+        let vb = weights::load_var_builder(Path::new(&model_path), options.dtype.into(), &device)?;
+        let model = CrossEncoderModel::load(&config, num_labels, vb)?;
+
+        // 句对超长时从较长的一段截断，保证 query 和文档都有保留
+        let max_seq_len = config.max_seq_len();
+        let mut plain_tokenizer = Tokenizer::from_file(format!("{}/tokenizer.json", model_path))
+            .map_err(|e| BackendError::Model(format!("Failed to load tokenizer: {}", e)))?;
+        plain_tokenizer
+            .with_truncation(None)
+            .map_err(|e| BackendError::Model(format!("Failed to disable truncation: {}", e)))?;
+        plain_tokenizer.with_padding(None);
+        let mut tokenizer = plain_tokenizer.clone();
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: max_seq_len,
+                strategy: TruncationStrategy::LongestFirst,
+                stride: 0,
+                direction: TruncationDirection::Right,
+            }))
+            .map_err(|e| BackendError::Model(format!("Failed to set truncation: {}", e)))?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            plain_tokenizer: Arc::new(plain_tokenizer),
+            device,
+            model_path,
+            max_seq_len,
+            pad_token_id: config.pad_token_id(),
+            pool: Arc::new(ComputePool::new(options.compute_threads)?),
+        })
+    }
+
+    // 按长度排序后分批打分，返回与文档顺序一致的分数和 token 数
+    fn score(&self, query: &str, documents: &[String], batch_size: u32) -> Result<RerankScores, BackendError> {
+        let encodings = documents
+            .iter()
+            .enumerate()
+            .map(|(i, document)| {
+                self.tokenizer
+                    .encode((query, document.as_str()), true)
+                    .map_err(|e| BackendError::InvalidInput(format!("failed to tokenize document {}: {}", i, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let token_counts: Vec<usize> = encodings.iter().map(|e| e.len()).collect();
+        let (scores, logits) = score_by_length(&token_counts, batch_size as usize, |batch| {
+            let batch_encodings: Vec<&Encoding> = batch.iter().map(|&i| &encodings[i]).collect();
+            self.process_batch(&batch_encodings).map_err(|e| {
+                let mut batch_documents = batch.to_vec();
+                batch_documents.sort_unstable();
+                BackendError::Inference(format!("documents {:?}: {}", batch_documents, e))
+            })
+        })?;
+
+        Ok(RerankScores {
+            scores,
+            logits,
+            token_counts,
+        })
+    }
+
+    /// 返回每个句对的 (分数, 原始 logit)
+    fn process_batch(&self, encodings: &[&Encoding]) -> candle_core::Result<Vec<(f32, f32)>> {
+        let max_len = encodings.iter().map(|e| e.len()).max().unwrap_or(1);
+        let mut input_ids = Vec::with_capacity(encodings.len() * max_len);
+        let mut token_type_ids = Vec::with_capacity(encodings.len() * max_len);
+        let mut attention_mask = Vec::with_capacity(encodings.len() * max_len);
+        for encoding in encodings {
+            let padding = max_len - encoding.len();
+            input_ids.extend(encoding.get_ids().iter().map(|&id| id as i64));
+            input_ids.extend(std::iter::repeat_n(self.pad_token_id as i64, padding));
+            token_type_ids.extend(encoding.get_type_ids().iter().map(|&t| t as i64));
+            token_type_ids.extend(std::iter::repeat_n(0, padding));
+            attention_mask.extend(encoding.get_attention_mask().iter().map(|&m| m as i64));
+            attention_mask.extend(std::iter::repeat_n(0, padding));
+        }
+
+        let shape = (encodings.len(), max_len);
+        let input_ids = Tensor::from_vec(input_ids, shape, &self.device)?;
+        let token_type_ids = Tensor::from_vec(token_type_ids, shape, &self.device)?;
+        let attention_mask = Tensor::from_vec(attention_mask, shape, &self.device)?;
+        let logits = self.model.forward(&input_ids, &token_type_ids, &attention_mask)?;
+        select_scores(&logits)
+    }
+}
+
+/// 按 token 数升序分批打分，返回按原始文档顺序排列的 (分数, 原始 logit)
+///
+/// score_batch 接收一批文档下标，返回与下标顺序一致的结果。
+fn score_by_length<F>(
+    lengths: &[usize],
+    batch_size: usize,
+    mut score_batch: F,
+) -> Result<(Vec<f32>, Vec<f32>), BackendError>
+where
+    F: FnMut(&[usize]) -> Result<Vec<(f32, f32)>, BackendError>,
+{
+    let mut order: Vec<usize> = (0..lengths.len()).collect();
+    order.sort_by_key(|&i| lengths[i]);
+
+    let mut scores = vec![0f32; lengths.len()];
+    let mut logits = vec![0f32; lengths.len()];
+    for batch in order.chunks(batch_size.max(1)) {
+        for (&i, (score, logit)) in batch.iter().zip(score_batch(batch)?) {
+            scores[i] = score;
+            logits[i] = logit;
+        }
+    }
+    Ok((scores, logits))
+}
+
+/// 将 (batch, num_labels) 的 logits 转换为每个句对的 (分数, 原始 logit)
+///
+/// 单标签模型取 sigmoid，多标签模型取最后一个标签（通常为“相关”）的 softmax 概率。
+fn select_scores(logits: &Tensor) -> candle_core::Result<Vec<(f32, f32)>> {
+    let last = logits.dim(D::Minus1)? - 1;
+    let scores = if last == 0 {
+        candle_nn::ops::sigmoid(logits)?
+    } else {
+        candle_nn::ops::softmax_last_dim(logits)?.narrow(D::Minus1, last, 1)?
+    };
+    let scores: Vec<f32> = scores.squeeze(D::Minus1)?.to_vec1()?;
+    let raw: Vec<f32> = logits.narrow(D::Minus1, last, 1)?.squeeze(D::Minus1)?.to_vec1()?;
+    Ok(scores.into_iter().zip(raw).collect())
+}
+
+#[async_trait]
+impl EmbeddingBackend for CrossEncoderBackend {
+    async fn embed(
+        &self,
+        _inputs: Vec<EmbedInput>,
+        _normalize_embeddings: bool,
+        _batch_size: u32,
+        _truncation: Truncation,
+        _prompt_name: Option<String>,
+    ) -> Result<EmbedResponse, BackendError> {
+        Err(BackendError::InvalidInput(format!(
+            "{} is a cross-encoder model and only supports rerank",
+            self.model_path
+        )))
+    }
+
+    async fn rerank(
+        &self,
+        query: String,
+        documents: Vec<String>,
+        batch_size: u32,
+    ) -> Result<RerankScores, BackendError> {
+        let backend = self.clone();
+        self.pool
+            .run(move || backend.score(&query, &documents, batch_size))
+            .await
+    }
+
+    fn tokenize(
+        &self,
+        inputs: Vec<String>,
+        add_special_tokens: bool,
+        _prompt_name: Option<&str>,
+    ) -> Result<Vec<Tokenized>, BackendError> {
+        // 单段文本的截断按单独送入模型计算；句对打分时截断两段中较长的一段
+        inputs
+            .iter()
+            .map(|text| tokenize_text(&self.plain_tokenizer, "", text, add_special_tokens, Some(self.max_seq_len)))
+            .collect()
+    }
+
+    fn detokenize(
+        &self,
+        inputs: Vec<Vec<u32>>,
+        skip_special_tokens: bool,
+    ) -> Result<Vec<String>, BackendError> {
+        inputs
+            .iter()
+            .map(|ids| detokenize_ids(&self.plain_tokenizer, ids, skip_special_tokens))
+            .collect()
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            max_seq_len: Some(self.max_seq_len),
+            task: ModelTask::Rerank,
+            ..ModelInfo::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn single_label_scores_use_sigmoid() {
+        let logits = Tensor::new(&[[0f32], [2.0]], &Device::Cpu).unwrap();
+        let (scores, raw): (Vec<f32>, Vec<f32>) = select_scores(&logits).unwrap().into_iter().unzip();
+        assert_close(&scores, &[0.5, 1.0 / (1.0 + (-2f32).exp())]);
+        assert_close(&raw, &[0.0, 2.0]);
+    }
+
+    #[test]
+    fn multi_label_scores_use_softmax_of_the_last_label() {
+        let logits = Tensor::new(&[[0f32, 0.0], [0.0, 3f32.ln()]], &Device::Cpu).unwrap();
+        let (scores, raw): (Vec<f32>, Vec<f32>) = select_scores(&logits).unwrap().into_iter().unzip();
+        assert_close(&scores, &[0.5, 0.75]);
+        assert_close(&raw, &[0.0, 3f32.ln()]);
+    }
+
+    #[test]
+    fn scores_are_returned_in_document_order() {
+        let lengths = [5, 1, 4, 2, 3];
+        let mut batches = Vec::new();
+        // 分数取文档长度，便于核对顺序
+        let (scores, logits) = score_by_length(&lengths, 2, |batch| {
+            batches.push(batch.to_vec());
+            Ok(batch.iter().map(|&i| (lengths[i] as f32, -(lengths[i] as f32))).collect())
+        })
+        .unwrap();
+
+        assert_eq!(batches, vec![vec![1, 3], vec![4, 2], vec![0]]);
+        assert_eq!(scores, vec![5.0, 1.0, 4.0, 2.0, 3.0]);
+        assert_eq!(logits, vec![-5.0, -1.0, -4.0, -2.0, -3.0]);
+    }
+}
@@ -0,0 +1,327 @@
+use candle_core::{DType, Device, Module, Tensor};
+use candle_nn::{Activation, Embedding, RmsNorm};
+use candle_transformers::utils::repeat_kv;
+use serde::Deserialize;
+
+use super::weights::{Linear, WeightLoader};
+
+/// Qwen2 / Qwen3 / Mistral / Llama 风格的 decoder-only 模型结构
+///
+/// 只做一次完整的前向计算、不保留 KV cache，因此可以在多个 worker 间共享。
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecoderConfig {
+    pub vocab_size: usize,
+    pub hidden_size: usize,
+    pub intermediate_size: usize,
+    pub num_hidden_layers: usize,
+    pub num_attention_heads: usize,
+    #[serde(default)]
+    pub num_key_value_heads: Option<usize>,
+    #[serde(default)]
+    pub head_dim: Option<usize>,
+    pub max_position_embeddings: usize,
+    #[serde(default = "default_rope_theta")]
+    pub rope_theta: f64,
+    #[serde(default = "default_rms_norm_eps")]
+    pub rms_norm_eps: f64,
+    #[serde(default = "default_hidden_act")]
+    pub hidden_act: Activation,
+    #[serde(default)]
+    pub attention_bias: bool,
+    #[serde(default)]
+    pub pad_token_id: Option<u32>,
+    #[serde(default)]
+    pub eos_token_id: Option<serde_json::Value>,
+    #[serde(default)]
+    pub rope_scaling: Option<serde_json::Value>,
+    #[serde(default)]
+    pub sliding_window: Option<usize>,
+    // Qwen2 / Qwen3 的 config.json 总带有 sliding_window，只有 use_sliding_window 为 true 时才生效
+    #[serde(default)]
+    pub use_sliding_window: Option<bool>,
+    // 由 model_type 决定，不从 config.json 读取
+    #[serde(skip)]
+    pub qk_norm: bool,
+    // llama 架构的 GGUF 转换时重排了 q/k 权重，需要交错式 RoPE
+    #[serde(skip)]
+    pub rope_interleaved: bool,
+}
+
+fn default_rope_theta() -> f64 {
+    10_000.0
+}
+
+fn default_rms_norm_eps() -> f64 {
+    1e-6
+}
+
+fn default_hidden_act() -> Activation {
+    Activation::Silu
+}
+
+impl DecoderConfig {
+    /// 根据 model_type 补全 config.json 中没有体现的结构差异
+    pub fn apply_model_type(&mut self, model_type: &str) {
+        match model_type {
+            // Qwen2 的 q/k/v 投影始终带 bias
+            "qwen2" => self.attention_bias = true,
+            // Qwen3 在 RoPE 之前对 q/k 做 RMSNorm
+            "qwen3" => self.qk_norm = true,
+            _ => {}
+        }
+    }
+
+    pub fn num_kv_heads(&self) -> usize {
+        self.num_key_value_heads.unwrap_or(self.num_attention_heads)
+    }
+
+    pub fn head_dim(&self) -> usize {
+        self.head_dim
+            .unwrap_or(self.hidden_size / self.num_attention_heads)
+    }
+
+    /// rope_scaling 的类型，兼容 rope_type 与旧版的 type 字段
+    pub fn rope_scaling_type(&self) -> Option<&str> {
+        let scaling = self.rope_scaling.as_ref()?;
+        Some(
+            scaling
+                .get("rope_type")
+                .or_else(|| scaling.get("type"))
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("unknown"),
+        )
+    }
+
+    /// 实际生效的滑动窗口长度，未启用时为 None
+    pub fn effective_sliding_window(&self) -> Option<usize> {
+        self.sliding_window
+            .filter(|_| self.use_sliding_window.unwrap_or(true))
+    }
+
+    /// 填充用的 token id：优先 pad_token_id，其次 eos_token_id
+    ///
+    /// 右侧填充配合因果注意力时，填充值不会影响真实 token 的隐藏状态。
+    pub fn pad_token_id(&self) -> u32 {
+        if let Some(id) = self.pad_token_id {
+            return id;
+        }
+        match &self.eos_token_id {
+            Some(serde_json::Value::Number(n)) => n.as_u64().unwrap_or(0) as u32,
+            Some(serde_json::Value::Array(ids)) => {
+                ids.first().and_then(|v| v.as_u64()).unwrap_or(0) as u32
+            }
+            _ => 0,
+        }
+    }
+}
+
+struct RotaryEmbedding {
+    inv_freq: Vec<f32>,
+}
+
+impl RotaryEmbedding {
+    fn new(cfg: &DecoderConfig) -> Self {
+        let dim = cfg.head_dim();
+        let inv_freq = (0..dim)
+            .step_by(2)
+            .map(|i| 1f32 / cfg.rope_theta.powf(i as f64 / dim as f64) as f32)
+            .collect();
+        Self { inv_freq }
+    }
+
+    fn cos_sin(&self, seq_len: usize, dtype: DType, device: &Device) -> candle_core::Result<(Tensor, Tensor)> {
+        let half = self.inv_freq.len();
+        let inv_freq = Tensor::from_vec(self.inv_freq.clone(), (1, half), device)?;
+        let positions = Tensor::arange(0u32, seq_len as u32, device)?
+            .to_dtype(DType::F32)?
+            .reshape((seq_len, 1))?;
+        let freqs = positions.matmul(&inv_freq)?;
+        Ok((freqs.cos()?.to_dtype(dtype)?, freqs.sin()?.to_dtype(dtype)?))
+    }
+}
+
+struct Attention {
+    q_proj: Linear,
+    k_proj: Linear,
+    v_proj: Linear,
+    o_proj: Linear,
+    q_norm: Option<RmsNorm>,
+    k_norm: Option<RmsNorm>,
+    num_heads: usize,
+    num_kv_heads: usize,
+    head_dim: usize,
+    rope_interleaved: bool,
+}
+
+impl Attention {
+    fn new(cfg: &DecoderConfig, vb: &WeightLoader) -> candle_core::Result<Self> {
+        let head_dim = cfg.head_dim();
+        let num_heads = cfg.num_attention_heads;
+        let num_kv_heads = cfg.num_kv_heads();
+        let bias = cfg.attention_bias;
+        let (q_norm, k_norm) = if cfg.qk_norm {
+            (
+                Some(vb.rms_norm("self_attn.q_norm", "attn_q_norm", head_dim, cfg.rms_norm_eps)?),
+                Some(vb.rms_norm("self_attn.k_norm", "attn_k_norm", head_dim, cfg.rms_norm_eps)?),
+            )
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            q_proj: vb.linear("self_attn.q_proj", "attn_q", cfg.hidden_size, num_heads * head_dim, bias)?,
+            k_proj: vb.linear("self_attn.k_proj", "attn_k", cfg.hidden_size, num_kv_heads * head_dim, bias)?,
+            v_proj: vb.linear("self_attn.v_proj", "attn_v", cfg.hidden_size, num_kv_heads * head_dim, bias)?,
+            o_proj: vb.linear("self_attn.o_proj", "attn_output", num_heads * head_dim, cfg.hidden_size, false)?,
+            q_norm,
+            k_norm,
+            num_heads,
+            num_kv_heads,
+            head_dim,
+            rope_interleaved: cfg.rope_interleaved,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, cos: &Tensor, sin: &Tensor, mask: &Tensor) -> candle_core::Result<Tensor> {
+        let (b_sz, seq_len, _) = xs.dims3()?;
+        let split_heads = |t: Tensor, heads: usize| {
+            t.reshape((b_sz, seq_len, heads, self.head_dim))?
+                .transpose(1, 2)?
+                .contiguous()
+        };
+        let mut q = split_heads(self.q_proj.forward(xs)?, self.num_heads)?;
+        let mut k = split_heads(self.k_proj.forward(xs)?, self.num_kv_heads)?;
+        let v = split_heads(self.v_proj.forward(xs)?, self.num_kv_heads)?;
+        if let (Some(q_norm), Some(k_norm)) = (&self.q_norm, &self.k_norm) {
+            q = q_norm.forward(&q)?;
+            k = k_norm.forward(&k)?;
+        }
+        let rope = if self.rope_interleaved {
+            candle_nn::rotary_emb::rope_i
+        } else {
+            candle_nn::rotary_emb::rope
+        };
+        let q = rope(&q, cos, sin)?;
+        let k = rope(&k, cos, sin)?;
+
+        let n_rep = self.num_heads / self.num_kv_heads;
+        let k = repeat_kv(k, n_rep)?.contiguous()?;
+        let v = repeat_kv(v, n_rep)?.contiguous()?;
+
+        let scale = 1.0 / (self.head_dim as f64).sqrt();
+        let scores = (q.matmul(&k.t()?)? * scale)?.broadcast_add(mask)?;
+        let probs = candle_nn::ops::softmax_last_dim(&scores)?;
+        probs
+            .matmul(&v)?
+            .transpose(1, 2)?
+            .reshape((b_sz, seq_len, self.num_heads * self.head_dim))?
+            .apply(&self.o_proj)
+    }
+}
+
+struct Mlp {
+    gate_proj: Linear,
+    up_proj: Linear,
+    down_proj: Linear,
+    act: Activation,
+}
+
+impl Mlp {
+    fn new(cfg: &DecoderConfig, vb: &WeightLoader) -> candle_core::Result<Self> {
+        let (hidden, inter) = (cfg.hidden_size, cfg.intermediate_size);
+        Ok(Self {
+            gate_proj: vb.linear("mlp.gate_proj", "ffn_gate", hidden, inter, false)?,
+            up_proj: vb.linear("mlp.up_proj", "ffn_up", hidden, inter, false)?,
+            down_proj: vb.linear("mlp.down_proj", "ffn_down", inter, hidden, false)?,
+            act: cfg.hidden_act,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        let gate = self.gate_proj.forward(xs)?.apply(&self.act)?;
+        (gate * self.up_proj.forward(xs)?)?.apply(&self.down_proj)
+    }
+}
+
+struct DecoderLayer {
+    self_attn: Attention,
+    mlp: Mlp,
+    input_layernorm: RmsNorm,
+    post_attention_layernorm: RmsNorm,
+}
+
+impl DecoderLayer {
+    fn new(cfg: &DecoderConfig, vb: &WeightLoader) -> candle_core::Result<Self> {
+        let (hidden, eps) = (cfg.hidden_size, cfg.rms_norm_eps);
+        Ok(Self {
+            self_attn: Attention::new(cfg, vb)?,
+            mlp: Mlp::new(cfg, vb)?,
+            input_layernorm: vb.rms_norm("input_layernorm", "attn_norm", hidden, eps)?,
+            post_attention_layernorm: vb.rms_norm("post_attention_layernorm", "ffn_norm", hidden, eps)?,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, cos: &Tensor, sin: &Tensor, mask: &Tensor) -> candle_core::Result<Tensor> {
+        let residual = xs;
+        let xs = self
+            .self_attn
+            .forward(&self.input_layernorm.forward(xs)?, cos, sin, mask)?;
+        let xs = (xs + residual)?;
+        let residual = &xs;
+        let mlp_out = self.mlp.forward(&self.post_attention_layernorm.forward(&xs)?)?;
+        mlp_out + residual
+    }
+}
+
+/// 输出最后一层（经过 final norm）的隐藏状态，供 last-token 等池化使用
+pub struct DecoderModel {
+    embed_tokens: Embedding,
+    layers: Vec<DecoderLayer>,
+    norm: RmsNorm,
+    rotary: RotaryEmbedding,
+    dtype: DType,
+}
+
+impl DecoderModel {
+    pub fn load(cfg: &DecoderConfig, vb: WeightLoader) -> candle_core::Result<Self> {
+        // 嵌入模型通常以 XxxModel 保存，权重没有 CausalLM 的 model. 前缀
+        let vb = match vb {
+            WeightLoader::Dense(inner) if inner.contains_tensor("model.embed_tokens.weight") => {
+                WeightLoader::Dense(inner.pp("model"))
+            }
+            vb => vb,
+        };
+        let embed_tokens = vb.embedding("embed_tokens", "token_embd", cfg.vocab_size, cfg.hidden_size)?;
+        let layers = (0..cfg.num_hidden_layers)
+            .map(|i| DecoderLayer::new(cfg, &vb.layer("layers", i)))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let norm = vb.rms_norm("norm", "output_norm", cfg.hidden_size, cfg.rms_norm_eps)?;
+        Ok(Self {
+            embed_tokens,
+            layers,
+            norm,
+            rotary: RotaryEmbedding::new(cfg),
+            dtype: vb.dtype(),
+        })
+    }
+
+    /// 输入需右侧填充：因果注意力下真实 token 看不到其后的填充位置
+    pub fn forward(&self, input_ids: &Tensor) -> candle_core::Result<Tensor> {
+        let (_b_sz, seq_len) = input_ids.dims2()?;
+        let device = input_ids.device();
+        let (cos, sin) = self.rotary.cos_sin(seq_len, self.dtype, device)?;
+        let mask = causal_mask(seq_len, self.dtype, device)?;
+
+        let mut xs = self.embed_tokens.forward(input_ids)?;
+        for layer in &self.layers {
+            xs = layer.forward(&xs, &cos, &sin, &mask)?;
+        }
+        self.norm.forward(&xs)
+    }
+}
+
+fn causal_mask(seq_len: usize, dtype: DType, device: &Device) -> candle_core::Result<Tensor> {
+    let mask: Vec<f32> = (0..seq_len)
+        .flat_map(|i| (0..seq_len).map(move |j| if j > i { f32::NEG_INFINITY } else { 0.0 }))
+        .collect();
+    Tensor::from_vec(mask, (1, 1, seq_len, seq_len), device)?.to_dtype(dtype)
+}
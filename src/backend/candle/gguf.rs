@@ -0,0 +1,249 @@
+use candle_core::quantized::gguf_file::{Content, Value as GgufValue};
+use candle_core::Device;
+use candle_transformers::quantized_var_builder::VarBuilder as QVarBuilder;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::model_config::{bert_config_from_json, decoder_config_from_json, ModelConfig};
+use super::pooling::Pooling;
+use crate::backend::BackendError;
+
+/// 从 GGUF 文件读出的模型结构、量化权重与元信息
+pub struct GgufModel {
+    pub config: ModelConfig,
+    pub weights: QVarBuilder,
+    /// 线性层权重的量化类型，如 Q8_0、Q4_K
+    pub quantization: String,
+    /// bert.pooling_type 给出的池化方式
+    pub pooling: Option<Pooling>,
+}
+
+/// 定位 GGUF 文件：可以直接指向 .gguf 文件，也可以是只包含一个 .gguf 文件的目录
+///
+/// 返回 GGUF 文件路径和模型目录，tokenizer.json、modules.json 等从模型目录读取。
+pub fn resolve_gguf_path(model_path: &str) -> Result<(PathBuf, String), BackendError> {
+    let path = Path::new(model_path);
+    if path.is_file() {
+        let dir = path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| ".".to_string());
+        return Ok((path.to_path_buf(), dir));
+    }
+
+    let entries = std::fs::read_dir(path)
+        .map_err(|e| BackendError::Model(format!("Failed to read {}: {}", path.display(), e)))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "gguf"))
+        .collect();
+    match files.len() {
+        1 => Ok((files.remove(0), model_path.to_string())),
+        0 => Err(BackendError::Model(format!(
+            "No .gguf file found in {}",
+            path.display()
+        ))),
+        _ => Err(BackendError::Model(format!(
+            "Multiple .gguf files found in {}, set MODEL_PATH to one of them",
+            path.display()
+        ))),
+    }
+}
+
+impl GgufModel {
+    pub fn load(path: &Path, device: &Device) -> Result<Self, BackendError> {
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| BackendError::Model(format!("Failed to open {}: {}", path.display(), e)))?;
+        let content = Content::read(&mut file)
+            .map_err(|e| BackendError::Model(format!("Failed to parse {}: {}", path.display(), e)))?;
+
+        let metadata = Metadata {
+            values: &content.metadata,
+        };
+        let arch = metadata.string("general.architecture")?;
+        let config = match arch.as_str() {
+            "bert" => ModelConfig::Bert(bert_config(&metadata, &content)?),
+            "qwen2" | "qwen3" | "llama" => ModelConfig::Decoder(decoder_config(&metadata, &content, &arch)?),
+            other => {
+                return Err(BackendError::Model(format!(
+                    "Unsupported GGUF architecture: {}",
+                    other
+                )))
+            }
+        };
+        let pooling = match metadata.get_u64(&format!("{}.pooling_type", arch)) {
+            Some(1) => Some(Pooling::Mean),
+            Some(2) => Some(Pooling::Cls),
+            Some(3) => Some(Pooling::LastToken),
+            _ => None,
+        };
+        let quantization = quantization_type(&content);
+
+        let weights = QVarBuilder::from_gguf(path, device)
+            .map_err(|e| BackendError::Model(format!("Failed to load {}: {}", path.display(), e)))?;
+
+        Ok(Self {
+            config,
+            weights,
+            quantization,
+            pooling,
+        })
+    }
+}
+
+// GGUF 元数据的整数可能以 u32 / u64 / i32 等任意类型存储
+struct Metadata<'a> {
+    values: &'a HashMap<String, GgufValue>,
+}
+
+impl Metadata<'_> {
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        match self.values.get(key)? {
+            GgufValue::U8(v) => Some(*v as u64),
+            GgufValue::U16(v) => Some(*v as u64),
+            GgufValue::U32(v) => Some(*v as u64),
+            GgufValue::U64(v) => Some(*v),
+            GgufValue::I8(v) => u64::try_from(*v).ok(),
+            GgufValue::I16(v) => u64::try_from(*v).ok(),
+            GgufValue::I32(v) => u64::try_from(*v).ok(),
+            GgufValue::I64(v) => u64::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.values.get(key)? {
+            GgufValue::F32(v) => Some(*v as f64),
+            GgufValue::F64(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn required_u64(&self, key: &str) -> Result<u64, BackendError> {
+        self.get_u64(key)
+            .ok_or_else(|| BackendError::Model(format!("GGUF metadata is missing {}", key)))
+    }
+
+    fn string(&self, key: &str) -> Result<String, BackendError> {
+        match self.values.get(key) {
+            Some(GgufValue::String(s)) => Ok(s.clone()),
+            _ => Err(BackendError::Model(format!("GGUF metadata is missing {}", key))),
+        }
+    }
+}
+
+// 词表大小等不一定写在元数据里，从张量形状读取
+fn tensor_rows(content: &Content, name: &str) -> Option<usize> {
+    content
+        .tensor_infos
+        .get(name)
+        .and_then(|info| info.shape.dims().first().copied())
+}
+
+fn bert_config(metadata: &Metadata, content: &Content) -> Result<candle_transformers::models::bert::Config, BackendError> {
+    let vocab_size = tensor_rows(content, "token_embd.weight")
+        .ok_or_else(|| BackendError::Model("GGUF is missing token_embd.weight".to_string()))?;
+    let mut raw = Map::new();
+    raw.insert("vocab_size".to_string(), json!(vocab_size));
+    raw.insert("hidden_size".to_string(), json!(metadata.required_u64("bert.embedding_length")?));
+    raw.insert("num_hidden_layers".to_string(), json!(metadata.required_u64("bert.block_count")?));
+    raw.insert(
+        "num_attention_heads".to_string(),
+        json!(metadata.required_u64("bert.attention.head_count")?),
+    );
+    raw.insert(
+        "intermediate_size".to_string(),
+        json!(metadata.required_u64("bert.feed_forward_length")?),
+    );
+    raw.insert(
+        "max_position_embeddings".to_string(),
+        json!(metadata.required_u64("bert.context_length")?),
+    );
+    if let Some(type_vocab_size) = tensor_rows(content, "token_types.weight") {
+        raw.insert("type_vocab_size".to_string(), json!(type_vocab_size));
+    }
+    if let Some(eps) = metadata.get_f64("bert.attention.layer_norm_epsilon") {
+        raw.insert("layer_norm_eps".to_string(), json!(eps));
+    }
+    if let Some(pad) = metadata.get_u64("tokenizer.ggml.padding_token_id") {
+        raw.insert("pad_token_id".to_string(), json!(pad));
+    }
+    bert_config_from_json(raw)
+}
+
+fn decoder_config(
+    metadata: &Metadata,
+    content: &Content,
+    arch: &str,
+) -> Result<super::decoder::DecoderConfig, BackendError> {
+    let key = |name: &str| format!("{}.{}", arch, name);
+    let vocab_size = tensor_rows(content, "token_embd.weight")
+        .ok_or_else(|| BackendError::Model("GGUF is missing token_embd.weight".to_string()))?;
+
+    let mut raw = Map::new();
+    raw.insert("vocab_size".to_string(), json!(vocab_size));
+    raw.insert("hidden_size".to_string(), json!(metadata.required_u64(&key("embedding_length"))?));
+    raw.insert(
+        "intermediate_size".to_string(),
+        json!(metadata.required_u64(&key("feed_forward_length"))?),
+    );
+    raw.insert("num_hidden_layers".to_string(), json!(metadata.required_u64(&key("block_count"))?));
+    raw.insert(
+        "num_attention_heads".to_string(),
+        json!(metadata.required_u64(&key("attention.head_count"))?),
+    );
+    raw.insert(
+        "max_position_embeddings".to_string(),
+        json!(metadata.required_u64(&key("context_length"))?),
+    );
+    if let Some(kv_heads) = metadata.get_u64(&key("attention.head_count_kv")) {
+        raw.insert("num_key_value_heads".to_string(), json!(kv_heads));
+    }
+    if let Some(head_dim) = metadata.get_u64(&key("attention.key_length")) {
+        raw.insert("head_dim".to_string(), json!(head_dim));
+    }
+    if let Some(theta) = metadata.get_f64(&key("rope.freq_base")) {
+        raw.insert("rope_theta".to_string(), json!(theta));
+    }
+    if let Some(window) = metadata.get_u64(&key("attention.sliding_window")) {
+        raw.insert("sliding_window".to_string(), json!(window));
+    }
+    if let Some(eps) = metadata.get_f64(&key("attention.layer_norm_rms_epsilon")) {
+        raw.insert("rms_norm_eps".to_string(), json!(eps));
+    }
+    if content.tensor_infos.contains_key("blk.0.attn_q.bias") {
+        raw.insert("attention_bias".to_string(), Value::Bool(true));
+    }
+    if let Some(pad) = metadata.get_u64("tokenizer.ggml.padding_token_id") {
+        raw.insert("pad_token_id".to_string(), json!(pad));
+    }
+    if let Some(eos) = metadata.get_u64("tokenizer.ggml.eos_token_id") {
+        raw.insert("eos_token_id".to_string(), json!(eos));
+    }
+
+    let mut config = decoder_config_from_json(raw, arch)?;
+    config.rope_interleaved = arch == "llama";
+    Ok(config)
+}
+
+// 取二维及以上张量中最常见的类型，一维的 norm / bias 通常保持 f32
+fn quantization_type(content: &Content) -> String {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for info in content.tensor_infos.values() {
+        if info.shape.rank() >= 2 {
+            *counts.entry(format!("{:?}", info.ggml_dtype)).or_default() += 1;
+        }
+    }
+    let name = counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(name, _)| name)
+        .unwrap_or_else(|| "F32".to_string());
+    // candle 的 Debug 输出为 Q4K，按 llama.cpp 的习惯写作 Q4_K
+    match name.strip_suffix('K') {
+        Some(prefix) if !prefix.contains('_') => format!("{}_K", prefix),
+        _ => name,
+    }
+}
@@ -0,0 +1,150 @@
+use candle_core::{DType, Module, Tensor};
+use candle_nn::{embedding, layer_norm, Embedding, LayerNorm, VarBuilder};
+use candle_transformers::models::bert::{BertEncoder, BertModel, Config as BertConfig};
+use candle_transformers::models::xlm_roberta::XLMRobertaModel;
+use candle_transformers::quantized_var_builder::VarBuilder as QVarBuilder;
+
+use super::decoder::DecoderModel;
+use super::model_config::ModelConfig;
+use super::quantized_bert::QuantizedBertModel;
+use super::weights::WeightLoader;
+use crate::backend::BackendError;
+
+/// 已加载的模型，输出 (batch, seq, hidden) 的隐藏状态
+pub enum EmbeddingModel {
+    Bert(BertModel),
+    HalfBert(HalfBertModel),
+    XlmRoberta(XLMRobertaModel),
+    Decoder(DecoderModel),
+    QuantizedBert(QuantizedBertModel),
+}
+
+impl EmbeddingModel {
+    pub fn load(config: &ModelConfig, vb: VarBuilder) -> Result<Self, BackendError> {
+        let model = match config {
+            // candle 的 BertModel 在 f16 下构造 attention mask 会得到 NaN，降精度时自行构造 mask
+            ModelConfig::Bert(config) if vb.dtype() != DType::F32 => {
+                HalfBertModel::load(config, vb).map(Self::HalfBert)
+            }
+            ModelConfig::Bert(config) => BertModel::load(vb, config).map(Self::Bert),
+            // 部分 checkpoint 的权重带有 roberta. 前缀
+            ModelConfig::XlmRoberta(config) => XLMRobertaModel::new(config, vb.clone())
+                .or_else(|_| XLMRobertaModel::new(config, vb.pp("roberta")))
+                .map(Self::XlmRoberta),
+            ModelConfig::Decoder(config) => {
+                DecoderModel::load(config, WeightLoader::Dense(vb)).map(Self::Decoder)
+            }
+        };
+        model.map_err(|e| BackendError::Model(format!("Failed to load model: {}", e)))
+    }
+
+    /// 从 GGUF 量化权重加载，支持 BERT 与 decoder 架构
+    pub fn load_quantized(config: &ModelConfig, vb: QVarBuilder) -> Result<Self, BackendError> {
+        let model = match config {
+            ModelConfig::Bert(config) => {
+                QuantizedBertModel::load(config, WeightLoader::Quantized(vb)).map(Self::QuantizedBert)
+            }
+            ModelConfig::Decoder(config) => {
+                DecoderModel::load(config, WeightLoader::Quantized(vb)).map(Self::Decoder)
+            }
+            ModelConfig::XlmRoberta(_) => {
+                return Err(BackendError::Model(
+                    "GGUF XLM-RoBERTa models are not supported".to_string(),
+                ))
+            }
+        };
+        model.map_err(|e| BackendError::Model(format!("Failed to load quantized model: {}", e)))
+    }
+
+    pub fn forward(&self, input_ids: &Tensor, attention_mask: &Tensor) -> candle_core::Result<Tensor> {
+        let token_type_ids = input_ids.zeros_like()?;
+        self.forward_with_token_types(input_ids, &token_type_ids, attention_mask)
+    }
+
+    /// 句对输入（如 cross-encoder）用 token_type_ids 区分两段文本，decoder 与量化模型忽略该参数
+    pub fn forward_with_token_types(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: &Tensor,
+    ) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Bert(model) => model.forward(input_ids, token_type_ids, Some(attention_mask)),
+            Self::HalfBert(model) => model.forward(input_ids, token_type_ids, attention_mask),
+            // position_ids 由 candle 按 HF 的 create_position_ids_from_input_ids 从 padding_idx 推导，
+            // 因此输入必须用 config.json 中的 pad_token_id 填充
+            Self::XlmRoberta(model) => {
+                model.forward(input_ids, attention_mask, token_type_ids, None, None, None)
+            }
+            // 因果注意力 + 右侧填充，真实 token 不会看到填充位置
+            Self::Decoder(model) => model.forward(input_ids),
+            Self::QuantizedBert(model) => model.forward(input_ids, attention_mask),
+        }
+    }
+}
+
+/// 以 f16 / bf16 权重运行的 BERT
+///
+/// candle 的 BertModel 用 f32::MIN 构造 attention mask，转换到 f16 后变为 -inf，与 0 相乘得到 NaN。
+/// 这里复用 candle 的编码器层，只重写嵌入层和 mask 的构造，mask 取目标精度的最小有限值。
+pub struct HalfBertModel {
+    word_embeddings: Embedding,
+    position_embeddings: Embedding,
+    token_type_embeddings: Embedding,
+    embeddings_norm: LayerNorm,
+    encoder: BertEncoder,
+}
+
+impl HalfBertModel {
+    // 部分 checkpoint 的权重带有 bert. 前缀
+    fn load(config: &BertConfig, vb: VarBuilder) -> candle_core::Result<Self> {
+        Self::load_weights(config, vb.clone()).or_else(|_| Self::load_weights(config, vb.pp("bert")))
+    }
+
+    fn load_weights(config: &BertConfig, vb: VarBuilder) -> candle_core::Result<Self> {
+        let hidden = config.hidden_size;
+        let embeddings = vb.pp("embeddings");
+        Ok(Self {
+            word_embeddings: embedding(config.vocab_size, hidden, embeddings.pp("word_embeddings"))?,
+            position_embeddings: embedding(
+                config.max_position_embeddings,
+                hidden,
+                embeddings.pp("position_embeddings"),
+            )?,
+            token_type_embeddings: embedding(
+                config.type_vocab_size,
+                hidden,
+                embeddings.pp("token_type_embeddings"),
+            )?,
+            embeddings_norm: layer_norm(hidden, config.layer_norm_eps, embeddings.pp("LayerNorm"))?,
+            encoder: BertEncoder::load(vb.pp("encoder"), config)?,
+        })
+    }
+
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: &Tensor,
+    ) -> candle_core::Result<Tensor> {
+        let (_b_sz, seq_len) = input_ids.dims2()?;
+        let positions = Tensor::arange(0u32, seq_len as u32, input_ids.device())?;
+        let xs = self
+            .word_embeddings
+            .forward(input_ids)?
+            .add(&self.token_type_embeddings.forward(token_type_ids)?)?
+            .broadcast_add(&self.position_embeddings.forward(&positions)?)?;
+        let xs = self.embeddings_norm.forward(&xs)?;
+
+        // (batch, seq) -> (batch, 1, 1, seq)，填充位置加上该精度能表示的最小值
+        let dtype = xs.dtype();
+        let min = match dtype {
+            DType::F16 => -65504.0,
+            DType::BF16 => -3.389_531_4e38,
+            _ => f32::MIN as f64,
+        };
+        let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(1)?.unsqueeze(1)?;
+        let mask = ((1.0 - mask)? * min)?.to_dtype(dtype)?;
+        self.encoder.forward(&xs, &mask)
+    }
+}
@@ -0,0 +1,249 @@
+use candle_transformers::models::bert::Config as BertConfig;
+use candle_transformers::models::xlm_roberta::Config as XlmRobertaConfig;
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+
+use super::decoder::DecoderConfig;
+use super::pooling::Pooling;
+use crate::backend::BackendError;
+
+// 编码器架构必须在 config.json 中显式给出的字段
+const ENCODER_REQUIRED_FIELDS: &[&str] = &[
+    "vocab_size",
+    "hidden_size",
+    "num_hidden_layers",
+    "num_attention_heads",
+    "intermediate_size",
+    "max_position_embeddings",
+];
+
+/// 按 config.json 的 model_type 解析出的模型结构
+#[derive(Debug, Clone)]
+pub enum ModelConfig {
+    Bert(BertConfig),
+    XlmRoberta(XlmRobertaConfig),
+    Decoder(DecoderConfig),
+}
+
+impl ModelConfig {
+    /// 单条输入允许的最大 token 数
+    pub fn max_seq_len(&self) -> usize {
+        match self {
+            Self::Bert(config) => config.max_position_embeddings,
+            // XLM-R 的位置编号从 padding_idx + 1 开始，前 padding_idx + 1 个位置不可用
+            Self::XlmRoberta(config) => config
+                .max_position_embeddings
+                .saturating_sub(config.pad_token_id as usize + 1),
+            Self::Decoder(config) => config.max_position_embeddings,
+        }
+    }
+
+    /// Transformer 输出的隐藏层维度
+    pub fn hidden_size(&self) -> usize {
+        match self {
+            Self::Bert(config) => config.hidden_size,
+            Self::XlmRoberta(config) => config.hidden_size,
+            Self::Decoder(config) => config.hidden_size,
+        }
+    }
+
+    pub fn pad_token_id(&self) -> u32 {
+        match self {
+            Self::Bert(config) => config.pad_token_id as u32,
+            Self::XlmRoberta(config) => config.pad_token_id,
+            Self::Decoder(config) => config.pad_token_id(),
+        }
+    }
+
+    /// 模型目录没有池化配置时使用的默认策略
+    pub fn default_pooling(&self) -> Pooling {
+        match self {
+            Self::Bert(_) | Self::XlmRoberta(_) => Pooling::Cls,
+            // decoder 模型只有最后一个 token 看到了完整输入
+            Self::Decoder(_) => Pooling::LastToken,
+        }
+    }
+}
+
+/// 读取 config.json 并根据 model_type 选择模型结构
+pub fn load_model_config(model_path: &str) -> Result<ModelConfig, BackendError> {
+    let raw = read_config_json(model_path)?;
+    let model_type = raw
+        .get("model_type")
+        .and_then(Value::as_str)
+        .unwrap_or("bert")
+        .to_string();
+    match model_type.as_str() {
+        "bert" => bert_config_from_json(raw).map(ModelConfig::Bert),
+        "xlm-roberta" | "roberta" => xlm_roberta_config_from_json(raw).map(ModelConfig::XlmRoberta),
+        "qwen2" | "qwen3" | "mistral" | "llama" => {
+            decoder_config_from_json(raw, &model_type).map(ModelConfig::Decoder)
+        }
+        other => Err(BackendError::Model(format!("Unsupported model_type: {}", other))),
+    }
+}
+
+/// architectures 为 *ForSequenceClassification（cross-encoder）时返回分类标签数
+///
+/// 标签数取 id2label 的项数，未给出时与 transformers 的默认值一致为 2。
+pub fn sequence_classification_labels(raw: &Map<String, Value>) -> Option<usize> {
+    let architectures = raw.get("architectures")?.as_array()?;
+    let is_classifier = architectures
+        .iter()
+        .filter_map(Value::as_str)
+        .any(|arch| arch.ends_with("ForSequenceClassification"));
+    if !is_classifier {
+        return None;
+    }
+    let labels = raw
+        .get("id2label")
+        .and_then(Value::as_object)
+        .map_or(2, |labels| labels.len());
+    Some(labels)
+}
+
+/// 读取模型目录下的 config.json
+pub fn read_config_json(model_path: &str) -> Result<Map<String, Value>, BackendError> {
+    let path = Path::new(model_path).join("config.json");
+    let content = fs::read_to_string(&path).map_err(|e| {
+        BackendError::Model(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    let value: Value = serde_json::from_str(&content).map_err(|e| {
+        BackendError::Model(format!("Failed to parse {}: {}", path.display(), e))
+    })?;
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => Err(BackendError::Model(format!(
+            "{} is not a JSON object",
+            path.display()
+        ))),
+    }
+}
+
+pub fn bert_config_from_json(mut raw: Map<String, Value>) -> Result<BertConfig, BackendError> {
+    check_required_fields(&raw)?;
+    check_absolute_positions(&mut raw)?;
+
+    // 将 HF 的激活函数名映射到 candle 的 HiddenAct
+    let hidden_act = raw
+        .get("hidden_act")
+        .and_then(Value::as_str)
+        .unwrap_or("gelu");
+    let hidden_act = match hidden_act {
+        "gelu" => "gelu",
+        "gelu_new" | "gelu_fast" | "gelu_pytorch_tanh" => "geluapproximate",
+        "relu" => "relu",
+        other => {
+            return Err(BackendError::Model(format!(
+                "Unsupported hidden_act: {}",
+                other
+            )))
+        }
+    };
+    raw.insert("hidden_act".to_string(), Value::String(hidden_act.to_string()));
+
+    // 非必填字段按 HF BertConfig 的默认值补齐
+    let defaults = BertConfig::default();
+    fill_default(&mut raw, "type_vocab_size", defaults.type_vocab_size);
+    fill_default(&mut raw, "layer_norm_eps", defaults.layer_norm_eps);
+    fill_default(&mut raw, "pad_token_id", defaults.pad_token_id);
+    fill_default(&mut raw, "hidden_dropout_prob", defaults.hidden_dropout_prob);
+    fill_default(&mut raw, "initializer_range", defaults.initializer_range);
+
+    serde_json::from_value(Value::Object(raw))
+        .map_err(|e| BackendError::Model(format!("Invalid BERT config.json: {}", e)))
+}
+
+fn xlm_roberta_config_from_json(
+    mut raw: Map<String, Value>,
+) -> Result<XlmRobertaConfig, BackendError> {
+    check_required_fields(&raw)?;
+    check_absolute_positions(&mut raw)?;
+
+    // 非必填字段按 HF XLMRobertaConfig 的默认值补齐
+    fill_default(&mut raw, "hidden_act", "gelu");
+    fill_default(&mut raw, "type_vocab_size", 1);
+    fill_default(&mut raw, "layer_norm_eps", 1e-5);
+    fill_default(&mut raw, "pad_token_id", 1);
+    fill_default(&mut raw, "hidden_dropout_prob", 0.1);
+    fill_default(&mut raw, "attention_probs_dropout_prob", 0.1);
+
+    serde_json::from_value(Value::Object(raw))
+        .map_err(|e| BackendError::Model(format!("Invalid XLM-RoBERTa config.json: {}", e)))
+}
+
+pub fn decoder_config_from_json(
+    raw: Map<String, Value>,
+    model_type: &str,
+) -> Result<DecoderConfig, BackendError> {
+    let mut config: DecoderConfig = serde_json::from_value(Value::Object(raw))
+        .map_err(|e| BackendError::Model(format!("Invalid {} config.json: {}", model_type, e)))?;
+    config.apply_model_type(model_type);
+    if !config.num_attention_heads.is_multiple_of(config.num_kv_heads()) {
+        return Err(BackendError::Model(format!(
+            "num_attention_heads ({}) is not a multiple of num_key_value_heads ({})",
+            config.num_attention_heads,
+            config.num_kv_heads()
+        )));
+    }
+    // 只实现了标准 RoPE 和全局因果注意力，其他情况下长输入会得到错误的向量
+    if let Some(rope_type) = config.rope_scaling_type() {
+        if rope_type != "default" {
+            return Err(BackendError::Model(format!(
+                "Unsupported rope_scaling type: {}",
+                rope_type
+            )));
+        }
+    }
+    if let Some(window) = config.effective_sliding_window() {
+        if window < config.max_position_embeddings {
+            return Err(BackendError::Model(format!(
+                "sliding_window ({}) is shorter than max_position_embeddings ({}), sliding window attention is not supported",
+                window, config.max_position_embeddings
+            )));
+        }
+    }
+    Ok(config)
+}
+
+fn check_required_fields(raw: &Map<String, Value>) -> Result<(), BackendError> {
+    let missing: Vec<&str> = ENCODER_REQUIRED_FIELDS
+        .iter()
+        .copied()
+        .filter(|field| !raw.contains_key(*field))
+        .collect();
+    if !missing.is_empty() {
+        return Err(BackendError::Model(format!(
+            "config.json is missing required fields: {}",
+            missing.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+// candle 只实现了绝对位置编码
+fn check_absolute_positions(raw: &mut Map<String, Value>) -> Result<(), BackendError> {
+    let position_embedding_type = raw
+        .get("position_embedding_type")
+        .and_then(Value::as_str)
+        .unwrap_or("absolute")
+        .to_string();
+    if position_embedding_type != "absolute" {
+        return Err(BackendError::Model(format!(
+            "Unsupported position_embedding_type: {}",
+            position_embedding_type
+        )));
+    }
+    raw.insert(
+        "position_embedding_type".to_string(),
+        Value::String(position_embedding_type),
+    );
+    Ok(())
+}
+
+// 字段缺失或为 null 时写入默认值
+fn fill_default(raw: &mut Map<String, Value>, key: &str, value: impl Into<Value>) {
+    if raw.get(key).is_none_or(Value::is_null) {
+        raw.insert(key.to_string(), value.into());
+    }
+}
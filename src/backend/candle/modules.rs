@@ -0,0 +1,186 @@
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{linear_b, Linear, Module};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use super::weights::load_var_builder;
+use crate::backend::BackendError;
+
+// modules.json 中的一项
+#[derive(Debug, Deserialize)]
+struct ModuleEntry {
+    path: String,
+    #[serde(rename = "type")]
+    module_type: String,
+}
+
+// 2_Dense/config.json
+#[derive(Debug, Deserialize)]
+struct DenseConfig {
+    in_features: usize,
+    out_features: usize,
+    #[serde(default = "default_bias")]
+    bias: bool,
+    #[serde(default = "default_activation")]
+    activation_function: String,
+}
+
+fn default_bias() -> bool {
+    true
+}
+
+fn default_activation() -> String {
+    "torch.nn.modules.activation.Tanh".to_string()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DenseActivation {
+    Identity,
+    Tanh,
+    Relu,
+    Gelu,
+    Sigmoid,
+}
+
+impl DenseActivation {
+    fn from_torch_name(name: &str) -> Result<Self, BackendError> {
+        match name.rsplit('.').next().unwrap_or(name) {
+            "Identity" => Ok(Self::Identity),
+            "Tanh" => Ok(Self::Tanh),
+            "ReLU" => Ok(Self::Relu),
+            "GELU" => Ok(Self::Gelu),
+            "Sigmoid" => Ok(Self::Sigmoid),
+            other => Err(BackendError::Model(format!(
+                "Unsupported Dense activation: {}",
+                other
+            ))),
+        }
+    }
+
+    fn apply(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Identity => Ok(xs.clone()),
+            Self::Tanh => xs.tanh(),
+            Self::Relu => xs.relu(),
+            Self::Gelu => xs.gelu_erf(),
+            Self::Sigmoid => candle_nn::ops::sigmoid(xs),
+        }
+    }
+}
+
+/// Pooling 之后执行的 sentence-transformers 模块
+#[derive(Debug, Clone)]
+pub enum PostModule {
+    Dense {
+        linear: Linear,
+        activation: DenseActivation,
+    },
+    Normalize,
+}
+
+impl PostModule {
+    pub fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Dense { linear, activation } => activation.apply(&linear.forward(xs)?),
+            Self::Normalize => {
+                let norm = xs.sqr()?.sum_keepdim(1)?.sqrt()?.clamp(1e-12, f64::MAX)?;
+                xs.broadcast_div(&norm)
+            }
+        }
+    }
+}
+
+/// 从 modules.json 解析出的推理流水线
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub pooling_dir: String,
+    pub post_modules: Vec<PostModule>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            pooling_dir: "1_Pooling".to_string(),
+            post_modules: Vec::new(),
+        }
+    }
+}
+
+impl Pipeline {
+    /// 读取 modules.json，文件不存在时只包含 Transformer + Pooling
+    pub fn from_model_dir(model_path: &str, device: &Device) -> Result<Self, BackendError> {
+        let path = Path::new(model_path).join("modules.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).map_err(|e| {
+            BackendError::Model(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let entries: Vec<ModuleEntry> = serde_json::from_str(&content).map_err(|e| {
+            BackendError::Model(format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+
+        let mut pipeline = Self::default();
+        for entry in entries {
+            let module_type = entry
+                .module_type
+                .rsplit('.')
+                .next()
+                .unwrap_or(&entry.module_type);
+            match module_type {
+                "Transformer" => {}
+                "Pooling" => pipeline.pooling_dir = entry.path,
+                "Dense" => pipeline
+                    .post_modules
+                    .push(load_dense(model_path, &entry.path, device)?),
+                "Normalize" => pipeline.post_modules.push(PostModule::Normalize),
+                other => {
+                    return Err(BackendError::Model(format!(
+                        "Unsupported sentence-transformers module: {}",
+                        other
+                    )))
+                }
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// 池化向量经过各模块后的维度，最后一个 Dense 的输出维度决定结果
+    pub fn output_dim(&self, hidden_size: usize) -> usize {
+        self.post_modules
+            .iter()
+            .rev()
+            .find_map(|module| match module {
+                PostModule::Dense { linear, .. } => linear.weight().dims().first().copied(),
+                PostModule::Normalize => None,
+            })
+            .unwrap_or(hidden_size)
+    }
+
+    pub fn forward(&self, pooled: &Tensor) -> candle_core::Result<Tensor> {
+        let mut xs = pooled.clone();
+        for module in &self.post_modules {
+            xs = module.forward(&xs)?;
+        }
+        Ok(xs)
+    }
+}
+
+fn load_dense(model_path: &str, module_path: &str, device: &Device) -> Result<PostModule, BackendError> {
+    let dir = Path::new(model_path).join(module_path);
+    let config_path = dir.join("config.json");
+    let content = fs::read_to_string(&config_path).map_err(|e| {
+        BackendError::Model(format!("Failed to read {}: {}", config_path.display(), e))
+    })?;
+    let config: DenseConfig = serde_json::from_str(&content).map_err(|e| {
+        BackendError::Model(format!("Failed to parse {}: {}", config_path.display(), e))
+    })?;
+
+    let vb = load_var_builder(&dir, DType::F32, device)?;
+
+    let linear = linear_b(config.in_features, config.out_features, config.bias, vb.pp("linear"))
+        .map_err(|e| BackendError::Model(format!("Failed to load Dense weights: {}", e)))?;
+    let activation = DenseActivation::from_torch_name(&config.activation_function)?;
+    Ok(PostModule::Dense { linear, activation })
+}
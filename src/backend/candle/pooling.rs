@@ -0,0 +1,187 @@
+use candle_core::{DType, Tensor, D};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::backend::BackendError;
+
+/// 句向量的池化策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pooling {
+    #[default]
+    Cls,
+    Mean,
+    Max,
+    LastToken,
+    WeightedMean,
+}
+
+impl std::str::FromStr for Pooling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "cls" => Ok(Self::Cls),
+            "mean" => Ok(Self::Mean),
+            "max" => Ok(Self::Max),
+            "last_token" | "lasttoken" => Ok(Self::LastToken),
+            "weighted_mean" | "weightedmean" => Ok(Self::WeightedMean),
+            _ => Err(format!("Invalid pooling: {}", s)),
+        }
+    }
+}
+
+// sentence-transformers 的 1_Pooling/config.json
+#[derive(Debug, Default, Deserialize)]
+struct SentenceTransformersPooling {
+    #[serde(default)]
+    pooling_mode_cls_token: bool,
+    #[serde(default)]
+    pooling_mode_mean_tokens: bool,
+    #[serde(default)]
+    pooling_mode_max_tokens: bool,
+    #[serde(default)]
+    pooling_mode_lasttoken: bool,
+    #[serde(default)]
+    pooling_mode_weightedmean_tokens: bool,
+}
+
+impl Pooling {
+    /// 读取 sentence-transformers 的池化配置，文件不存在时返回 None
+    pub fn from_model_dir(model_path: &str, pooling_dir: &str) -> Result<Option<Self>, BackendError> {
+        let path = Path::new(model_path).join(pooling_dir).join("config.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path).map_err(|e| {
+            BackendError::Model(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let config: SentenceTransformersPooling = serde_json::from_str(&content).map_err(|e| {
+            BackendError::Model(format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+
+        let pooling = if config.pooling_mode_cls_token {
+            Self::Cls
+        } else if config.pooling_mode_mean_tokens {
+            Self::Mean
+        } else if config.pooling_mode_max_tokens {
+            Self::Max
+        } else if config.pooling_mode_lasttoken {
+            Self::LastToken
+        } else if config.pooling_mode_weightedmean_tokens {
+            Self::WeightedMean
+        } else {
+            return Err(BackendError::Model(format!(
+                "No supported pooling mode enabled in {}",
+                path.display()
+            )));
+        };
+        Ok(Some(pooling))
+    }
+
+    /// 将 (batch, seq, hidden) 的隐藏状态按 attention mask 池化为 (batch, hidden)
+    ///
+    /// 输入按右侧填充，mask 中 0 的位置不参与计算。
+    pub fn pool(&self, hidden_states: &Tensor, attention_mask: &Tensor) -> candle_core::Result<Tensor> {
+        let hidden_states = hidden_states.to_dtype(DType::F32)?;
+        let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(D::Minus1)?;
+
+        match self {
+            Self::Cls => hidden_states.narrow(1, 0, 1)?.squeeze(1),
+            Self::Mean => {
+                let summed = hidden_states.broadcast_mul(&mask)?.sum(1)?;
+                let counts = mask.sum(1)?.clamp(1e-9, f64::MAX)?;
+                summed.broadcast_div(&counts)
+            }
+            Self::Max => {
+                // 填充位置置为极小值，避免被 max 选中
+                let offset = ((&mask - 1.0)? * 1e9)?;
+                hidden_states.broadcast_add(&offset)?.max(1)
+            }
+            Self::LastToken => {
+                let lengths = attention_mask.to_dtype(DType::F32)?.sum(1)?.to_vec1::<f32>()?;
+                let rows = lengths
+                    .iter()
+                    .enumerate()
+                    .map(|(row, &len)| {
+                        let last = (len as usize).max(1) - 1;
+                        hidden_states.narrow(0, row, 1)?.narrow(1, last, 1)?.squeeze(1)
+                    })
+                    .collect::<candle_core::Result<Vec<_>>>()?;
+                Tensor::cat(&rows, 0)
+            }
+            Self::WeightedMean => {
+                // 与 sentence-transformers 一致，按位置 1..=seq_len 线性加权
+                let seq_len = hidden_states.dim(1)?;
+                let positions = Tensor::arange(1f32, seq_len as f32 + 1.0, hidden_states.device())?
+                    .reshape((1, seq_len, 1))?;
+                let weights = mask.broadcast_mul(&positions)?;
+                let summed = hidden_states.broadcast_mul(&weights)?.sum(1)?;
+                let total = weights.sum(1)?.clamp(1e-9, f64::MAX)?;
+                summed.broadcast_div(&total)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::Device;
+
+    // 两条输入，第二条最后一个位置是填充，填充位置的值很大以便发现泄漏
+    fn batch() -> (Tensor, Tensor) {
+        let hidden_states = Tensor::new(
+            &[
+                [[1f32, 2.0], [3.0, 4.0], [5.0, 6.0]],
+                [[1.0, 1.0], [3.0, 5.0], [100.0, 100.0]],
+            ],
+            &Device::Cpu,
+        )
+        .unwrap();
+        let attention_mask = Tensor::new(&[[1u32, 1, 1], [1, 1, 0]], &Device::Cpu).unwrap();
+        (hidden_states, attention_mask)
+    }
+
+    fn pool(pooling: Pooling) -> Vec<Vec<f32>> {
+        let (hidden_states, attention_mask) = batch();
+        pooling
+            .pool(&hidden_states, &attention_mask)
+            .unwrap()
+            .to_vec2()
+            .unwrap()
+    }
+
+    fn assert_close(actual: Vec<Vec<f32>>, expected: &[[f32; 2]; 2]) {
+        for (row, expected) in actual.iter().zip(expected) {
+            for (a, e) in row.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn mean_excludes_padding() {
+        assert_close(pool(Pooling::Mean), &[[3.0, 4.0], [2.0, 3.0]]);
+    }
+
+    #[test]
+    fn max_excludes_padding() {
+        assert_close(pool(Pooling::Max), &[[5.0, 6.0], [3.0, 5.0]]);
+    }
+
+    #[test]
+    fn weighted_mean_excludes_padding() {
+        // 位置权重 1, 2, 3；第二条只有前两个位置参与
+        assert_close(
+            pool(Pooling::WeightedMean),
+            &[[22.0 / 6.0, 28.0 / 6.0], [7.0 / 3.0, 11.0 / 3.0]],
+        );
+    }
+
+    #[test]
+    fn last_token_picks_the_last_unmasked_position() {
+        assert_close(pool(Pooling::LastToken), &[[5.0, 6.0], [3.0, 5.0]]);
+    }
+}
@@ -0,0 +1,147 @@
+use candle_core::{DType, Module, Tensor, D};
+use candle_nn::{Embedding, LayerNorm};
+use candle_transformers::models::bert::{Config as BertConfig, HiddenAct};
+
+use super::weights::{Linear, WeightLoader};
+
+/// 使用量化线性层的 BERT 编码器
+///
+/// candle 的 BertModel 只接受浮点权重，这里按同样的结构重写一遍前向计算，
+/// 矩阵乘法走量化内核，LayerNorm 与嵌入表在加载时反量化为 f32。
+pub struct QuantizedBertModel {
+    word_embeddings: Embedding,
+    position_embeddings: Embedding,
+    token_type_embeddings: Option<Embedding>,
+    embeddings_norm: LayerNorm,
+    layers: Vec<BertLayer>,
+}
+
+struct BertLayer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    ffn_output: Linear,
+    output_norm: LayerNorm,
+    num_heads: usize,
+    head_dim: usize,
+    act: HiddenAct,
+}
+
+impl BertLayer {
+    fn new(config: &BertConfig, vb: &WeightLoader) -> candle_core::Result<Self> {
+        let hidden = config.hidden_size;
+        let inter = config.intermediate_size;
+        let eps = config.layer_norm_eps;
+        Ok(Self {
+            query: vb.linear("attention.self.query", "attn_q", hidden, hidden, true)?,
+            key: vb.linear("attention.self.key", "attn_k", hidden, hidden, true)?,
+            value: vb.linear("attention.self.value", "attn_v", hidden, hidden, true)?,
+            output: vb.linear("attention.output.dense", "attn_output", hidden, hidden, true)?,
+            attention_norm: vb.layer_norm("attention.output.LayerNorm", "attn_output_norm", hidden, eps)?,
+            intermediate: vb.linear("intermediate.dense", "ffn_up", hidden, inter, true)?,
+            ffn_output: vb.linear("output.dense", "ffn_down", inter, hidden, true)?,
+            output_norm: vb.layer_norm("output.LayerNorm", "layer_output_norm", hidden, eps)?,
+            num_heads: config.num_attention_heads,
+            head_dim: hidden / config.num_attention_heads,
+            act: config.hidden_act,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, mask: &Tensor) -> candle_core::Result<Tensor> {
+        let (b_sz, seq_len, hidden) = xs.dims3()?;
+        let split_heads = |t: Tensor| {
+            t.reshape((b_sz, seq_len, self.num_heads, self.head_dim))?
+                .transpose(1, 2)?
+                .contiguous()
+        };
+        let q = split_heads(self.query.forward(xs)?)?;
+        let k = split_heads(self.key.forward(xs)?)?;
+        let v = split_heads(self.value.forward(xs)?)?;
+
+        let scale = 1.0 / (self.head_dim as f64).sqrt();
+        let scores = (q.matmul(&k.t()?)? * scale)?.broadcast_add(mask)?;
+        let probs = candle_nn::ops::softmax(&scores, D::Minus1)?;
+        let context = probs
+            .matmul(&v)?
+            .transpose(1, 2)?
+            .reshape((b_sz, seq_len, hidden))?;
+        let xs = self.attention_norm.forward(&(self.output.forward(&context)? + xs)?)?;
+
+        let ffn = self.intermediate.forward(&xs)?;
+        let ffn = match self.act {
+            HiddenAct::Gelu => ffn.gelu_erf()?,
+            HiddenAct::GeluApproximate => ffn.gelu()?,
+            HiddenAct::Relu => ffn.relu()?,
+        };
+        self.output_norm.forward(&(self.ffn_output.forward(&ffn)? + xs)?)
+    }
+}
+
+impl QuantizedBertModel {
+    pub fn load(config: &BertConfig, vb: WeightLoader) -> candle_core::Result<Self> {
+        let hidden = config.hidden_size;
+        let word_embeddings = vb.embedding(
+            "embeddings.word_embeddings",
+            "token_embd",
+            config.vocab_size,
+            hidden,
+        )?;
+        let position_embeddings = vb.embedding(
+            "embeddings.position_embeddings",
+            "position_embd",
+            config.max_position_embeddings,
+            hidden,
+        )?;
+        // 部分 GGUF 转换会省略 token_type 表，此时不加这一项
+        let token_type_embeddings = vb
+            .embedding(
+                "embeddings.token_type_embeddings",
+                "token_types",
+                config.type_vocab_size,
+                hidden,
+            )
+            .ok();
+        let embeddings_norm = vb.layer_norm(
+            "embeddings.LayerNorm",
+            "token_embd_norm",
+            hidden,
+            config.layer_norm_eps,
+        )?;
+        let layers = (0..config.num_hidden_layers)
+            .map(|i| BertLayer::new(config, &vb.layer("encoder.layer", i)))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        Ok(Self {
+            word_embeddings,
+            position_embeddings,
+            token_type_embeddings,
+            embeddings_norm,
+            layers,
+        })
+    }
+
+    pub fn forward(&self, input_ids: &Tensor, attention_mask: &Tensor) -> candle_core::Result<Tensor> {
+        let (_b_sz, seq_len) = input_ids.dims2()?;
+        let device = input_ids.device();
+
+        let positions = Tensor::arange(0u32, seq_len as u32, device)?.unsqueeze(0)?;
+        let mut xs = self
+            .word_embeddings
+            .forward(input_ids)?
+            .broadcast_add(&self.position_embeddings.forward(&positions)?)?;
+        if let Some(token_types) = &self.token_type_embeddings {
+            xs = xs.broadcast_add(&token_types.forward(&input_ids.zeros_like()?)?)?;
+        }
+        let mut xs = self.embeddings_norm.forward(&xs)?;
+
+        // (batch, seq) -> (batch, 1, 1, seq)，填充位置加一个极小值
+        let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(1)?.unsqueeze(1)?;
+        let mask = ((1.0 - mask)? * f32::MIN as f64)?;
+        for layer in &self.layers {
+            xs = layer.forward(&xs, &mask)?;
+        }
+        Ok(xs)
+    }
+}
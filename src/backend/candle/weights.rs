@@ -0,0 +1,154 @@
+use candle_core::{DType, Device, Module, Tensor};
+use candle_nn::{Embedding, LayerNorm, RmsNorm, VarBuilder};
+use candle_transformers::quantized_nn;
+use candle_transformers::quantized_var_builder::VarBuilder as QVarBuilder;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::backend::BackendError;
+
+/// 打开目录下的浮点权重，按以下顺序查找：
+///
+/// 1. model.safetensors.index.json 列出的分片
+/// 2. model.safetensors
+/// 3. pytorch_model.bin
+///
+/// safetensors 通过 mmap 读取，多个进程加载同一模型时共享 page cache。
+pub fn load_var_builder(dir: &Path, dtype: DType, device: &Device) -> Result<VarBuilder<'static>, BackendError> {
+    let index_path = dir.join("model.safetensors.index.json");
+    let single_path = dir.join("model.safetensors");
+    let files = if index_path.exists() {
+        safetensors_shards(&index_path)?
+    } else if single_path.exists() {
+        vec![single_path]
+    } else {
+        let pth_path = dir.join("pytorch_model.bin");
+        if !pth_path.exists() {
+            return Err(BackendError::Model(format!(
+                "No model.safetensors, model.safetensors.index.json or pytorch_model.bin found in {}",
+                dir.display()
+            )));
+        }
+        return VarBuilder::from_pth(&pth_path, dtype, device).map_err(|e| {
+            BackendError::Model(format!("Failed to load {}: {}", pth_path.display(), e))
+        });
+    };
+
+    // SAFETY: 权重文件在服务运行期间不应被修改，这是 mmap 读取的前提
+    unsafe { VarBuilder::from_mmaped_safetensors(&files, dtype, device) }
+        .map_err(|e| BackendError::Model(format!("Failed to load weights from {}: {}", dir.display(), e)))
+}
+
+// 从 index.json 的 weight_map 中收集分片文件名，去重后按名称排序
+fn safetensors_shards(index_path: &Path) -> Result<Vec<PathBuf>, BackendError> {
+    let content = std::fs::read_to_string(index_path).map_err(|e| {
+        BackendError::Model(format!("Failed to read {}: {}", index_path.display(), e))
+    })?;
+    let index: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
+        BackendError::Model(format!("Failed to parse {}: {}", index_path.display(), e))
+    })?;
+    let weight_map = index
+        .get("weight_map")
+        .and_then(serde_json::Value::as_object)
+        .ok_or_else(|| {
+            BackendError::Model(format!("{} has no weight_map", index_path.display()))
+        })?;
+    let dir = index_path.parent().unwrap_or(Path::new("."));
+    let shards: BTreeSet<&str> = weight_map.values().filter_map(serde_json::Value::as_str).collect();
+    Ok(shards.into_iter().map(|file| dir.join(file)).collect())
+}
+
+/// 模型权重来源：safetensors 的浮点权重或 GGUF 的量化权重
+///
+/// 两者的张量命名不同，因此每次取权重都同时给出 HF 名称和 GGUF 名称。
+#[derive(Clone)]
+pub enum WeightLoader<'a> {
+    Dense(VarBuilder<'a>),
+    Quantized(QVarBuilder),
+}
+
+impl WeightLoader<'_> {
+    /// 进入第 i 层：HF 为 {prefix}.{i}，GGUF 统一为 blk.{i}
+    pub fn layer(&self, hf_prefix: &str, index: usize) -> Self {
+        match self {
+            Self::Dense(vb) => Self::Dense(vb.pp(hf_prefix).pp(index)),
+            Self::Quantized(vb) => Self::Quantized(vb.pp(format!("blk.{}", index))),
+        }
+    }
+
+    /// 计算使用的精度，量化权重的激活值固定为 f32
+    pub fn dtype(&self) -> DType {
+        match self {
+            Self::Dense(vb) => vb.dtype(),
+            Self::Quantized(_) => DType::F32,
+        }
+    }
+
+    pub fn linear(
+        &self,
+        hf_name: &str,
+        gguf_name: &str,
+        in_dim: usize,
+        out_dim: usize,
+        bias: bool,
+    ) -> candle_core::Result<Linear> {
+        match self {
+            Self::Dense(vb) => {
+                candle_nn::linear_b(in_dim, out_dim, bias, vb.pp(hf_name)).map(Linear::Dense)
+            }
+            Self::Quantized(vb) => {
+                quantized_nn::linear_b(in_dim, out_dim, bias, vb.pp(gguf_name)).map(Linear::Quantized)
+            }
+        }
+    }
+
+    pub fn embedding(
+        &self,
+        hf_name: &str,
+        gguf_name: &str,
+        rows: usize,
+        dim: usize,
+    ) -> candle_core::Result<Embedding> {
+        match self {
+            Self::Dense(vb) => candle_nn::embedding(rows, dim, vb.pp(hf_name)),
+            // 量化的词表在加载时反量化，查表不支持量化张量
+            Self::Quantized(vb) => {
+                let table = vb.pp(gguf_name).get((rows, dim), "weight")?.dequantize(vb.device())?;
+                Ok(Embedding::new(table, dim))
+            }
+        }
+    }
+
+    pub fn rms_norm(&self, hf_name: &str, gguf_name: &str, size: usize, eps: f64) -> candle_core::Result<RmsNorm> {
+        match self {
+            Self::Dense(vb) => candle_nn::rms_norm(size, eps, vb.pp(hf_name)),
+            Self::Quantized(vb) => {
+                let weight = vb.pp(gguf_name).get(size, "weight")?.dequantize(vb.device())?;
+                Ok(RmsNorm::new(weight, eps))
+            }
+        }
+    }
+
+    pub fn layer_norm(&self, hf_name: &str, gguf_name: &str, size: usize, eps: f64) -> candle_core::Result<LayerNorm> {
+        match self {
+            Self::Dense(vb) => candle_nn::layer_norm(size, eps, vb.pp(hf_name)),
+            Self::Quantized(vb) => quantized_nn::layer_norm(size, eps, vb.pp(gguf_name)),
+        }
+    }
+}
+
+/// 浮点或量化的线性层
+#[derive(Debug, Clone)]
+pub enum Linear {
+    Dense(candle_nn::Linear),
+    Quantized(quantized_nn::Linear),
+}
+
+impl Module for Linear {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Dense(linear) => linear.forward(xs),
+            Self::Quantized(linear) => linear.forward(xs),
+        }
+    }
+}
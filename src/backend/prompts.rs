@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::backend::BackendError;
+
+/// 文档侧的内置提示词名称：未在 prompts 中配置时表示不加前缀，
+/// 使 input_type="document" 等请求在只配置了查询指令的模型上也能使用
+const DOCUMENT_PROMPT_NAMES: [&str; 2] = ["document", "passage"];
+
+/// 命名的提示词前缀，与 sentence-transformers 的 prompts 相同，例如 {"query": "query: "}
+#[derive(Debug, Clone, Default)]
+pub struct Prompts {
+    prompts: HashMap<String, String>,
+    /// 请求未指定提示词时使用的名称
+    default_prompt_name: Option<String>,
+}
+
+// config_sentence_transformers.json 中与提示词相关的字段
+#[derive(Debug, Deserialize)]
+struct SentenceTransformersConfig {
+    #[serde(default)]
+    prompts: HashMap<String, String>,
+    #[serde(default)]
+    default_prompt_name: Option<String>,
+}
+
+impl Prompts {
+    pub fn new(prompts: HashMap<String, String>, default_prompt_name: Option<String>) -> Self {
+        Self {
+            prompts,
+            default_prompt_name,
+        }
+    }
+
+    /// 读取模型目录下 config_sentence_transformers.json 中的 prompts，文件不存在时为空
+    pub fn from_model_dir(model_path: &str) -> Result<Self, BackendError> {
+        let path = Path::new(model_path).join("config_sentence_transformers.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).map_err(|e| {
+            BackendError::Model(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let config: SentenceTransformersConfig = serde_json::from_str(&content).map_err(|e| {
+            BackendError::Model(format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(config.prompts, config.default_prompt_name))
+    }
+
+    /// 合并另一组提示词，同名时以 overrides 为准
+    pub fn merge(mut self, overrides: Prompts) -> Self {
+        self.prompts.extend(overrides.prompts);
+        if overrides.default_prompt_name.is_some() {
+            self.default_prompt_name = overrides.default_prompt_name;
+        }
+        self
+    }
+
+    /// 添加提示词，已有同名提示词时保留原值
+    pub fn insert_missing(&mut self, name: &str, prompt: String) {
+        self.prompts.entry(name.to_string()).or_insert(prompt);
+    }
+
+    /// 按名称查找前缀，未配置的 document / passage 返回空前缀，其他未知名称返回 InvalidInput
+    pub fn get(&self, name: &str) -> Result<&str, BackendError> {
+        if let Some(prompt) = self.prompts.get(name) {
+            return Ok(prompt);
+        }
+        if DOCUMENT_PROMPT_NAMES.contains(&name) {
+            return Ok("");
+        }
+        let mut names: Vec<&str> = self.prompts.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        Err(BackendError::InvalidInput(format!(
+            "unknown prompt_name: {}, available prompts: [{}]",
+            name,
+            names.join(", ")
+        )))
+    }
+
+    /// 请求未指定提示词时使用的默认前缀，空字符串视为不加前缀
+    pub fn default_prompt(&self) -> Option<&str> {
+        self.default_prompt_name
+            .as_deref()
+            .and_then(|name| self.prompts.get(name))
+            .map(String::as_str)
+            .filter(|prompt| !prompt.is_empty())
+    }
+}
@@ -1,7 +1,10 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::{env, fs, path::Path};
 
+use crate::backend::candle::{ModelDType, Pooling};
 use crate::backend::BackendType;
+use crate::types::Truncation;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -14,10 +17,220 @@ pub struct Config {
     pub batch_size: u32,
     pub workers: usize,
     pub queue_capacity: usize,
+    /// 队列满时的准入策略
+    #[serde(default)]
+    pub queue_policy: QueuePolicy,
+    /// wait 策略下等待入队的最长时间（毫秒），0 表示一直等待
+    #[serde(default)]
+    pub queue_timeout_ms: u64,
+    /// 因过载被拒绝时 Retry-After 响应头的秒数
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
     pub model_name: String,
+    #[serde(default)]
+    pub pooling: Option<Pooling>,
+    #[serde(default)]
+    pub instruction: Option<String>,
+    #[serde(default)]
+    pub dtype: ModelDType,
+    #[serde(default = "default_compute_threads")]
+    pub compute_threads: usize,
+    /// 单次前向计算内部算子使用的线程数，0 表示使用全部 CPU 核；对整个进程生效，不能按模型设置。
+    /// candle 的矩阵乘按 RAYON_NUM_THREADS（未设置时为 CPU 核数）切分任务，应设为相同的值
+    #[serde(default)]
+    pub intra_op_threads: usize,
+    #[serde(default = "default_batch_wait_ms")]
+    pub batch_wait_ms: u64,
+    #[serde(default = "default_max_batch_tokens")]
+    pub max_batch_tokens: usize,
+    #[serde(default)]
+    pub tokenizer_path: Option<String>,
+    /// proxy 后端单次请求的超时时间（毫秒），0 表示不限制
+    #[serde(default)]
+    pub backend_timeout_ms: u64,
+    #[serde(default)]
+    pub truncation: Truncation,
+    #[serde(default = "default_chunk_overlap")]
+    pub chunk_overlap: usize,
+    /// 命名提示词前缀，如 query = "query: "
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+    #[serde(default)]
+    pub default_prompt_name: Option<String>,
+    /// 开启 Hugging Face TEI 兼容接口（/embed、/embed_all、/rerank、/tokenize、/info）
+    #[serde(default)]
+    pub tei_compat: bool,
+    /// TEI 接口的路径前缀，如 "/tei"；为空时挂在根路径，并替换原有的 /embed
+    #[serde(default)]
+    pub tei_prefix: String,
+    /// 顶层模型的别名，请求中的 model 可以使用这些名称
+    #[serde(default)]
+    pub model_aliases: Vec<String>,
+    /// 同时加载的多个模型，为空时只加载顶层配置描述的模型
+    #[serde(default)]
+    pub models: Vec<ModelEntry>,
+}
+
+/// 队列已满时新请求的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePolicy {
+    /// 等待队列空出位置，最长等待 queue_timeout_ms，超时返回 503
+    #[default]
+    Wait,
+    /// 立即返回 429
+    Reject,
+    /// 丢弃最早排队的请求（返回 503），让新请求入队
+    ShedOldest,
+}
+
+impl std::str::FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wait" => Ok(Self::Wait),
+            "reject" => Ok(Self::Reject),
+            "shed_oldest" => Ok(Self::ShedOldest),
+            _ => Err(format!("Invalid queue policy: {}", s)),
+        }
+    }
+}
+
+/// 模型列表中的一项，未设置的字段沿用顶层配置
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModelEntry {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub backend_type: Option<BackendType>,
+    pub backend_url: Option<String>,
+    pub model_path: Option<String>,
+    pub tokenizer_path: Option<String>,
+    pub backend_timeout_ms: Option<u64>,
+    pub pooling: Option<Pooling>,
+    pub dtype: Option<ModelDType>,
+    pub instruction: Option<String>,
+    pub normalize_embeddings: Option<bool>,
+    pub batch_size: Option<u32>,
+    pub workers: Option<usize>,
+    pub queue_capacity: Option<usize>,
+    pub queue_policy: Option<QueuePolicy>,
+    pub queue_timeout_ms: Option<u64>,
+    pub compute_threads: Option<usize>,
+    pub batch_wait_ms: Option<u64>,
+    pub max_batch_tokens: Option<usize>,
+    pub truncation: Option<Truncation>,
+    pub chunk_overlap: Option<usize>,
+    pub prompts: Option<HashMap<String, String>>,
+    pub default_prompt_name: Option<String>,
+}
+
+impl ModelEntry {
+    // 以顶层配置为基础，覆盖本项设置的字段，得到单个模型的完整配置
+    fn apply(&self, base: &Config) -> Config {
+        let mut config = base.clone();
+        config.models = Vec::new();
+        config.model_name = self.name.clone();
+        config.model_aliases = self.aliases.clone();
+        if let Some(v) = &self.backend_type {
+            config.backend_type = v.clone();
+        }
+        if let Some(v) = &self.backend_url {
+            config.backend_url = v.clone();
+        }
+        if let Some(v) = &self.model_path {
+            config.model_path = v.clone();
+        }
+        if let Some(v) = &self.tokenizer_path {
+            config.tokenizer_path = Some(v.clone());
+        }
+        if let Some(v) = self.backend_timeout_ms {
+            config.backend_timeout_ms = v;
+        }
+        if let Some(v) = self.pooling {
+            config.pooling = Some(v);
+        }
+        if let Some(v) = self.dtype {
+            config.dtype = v;
+        }
+        if let Some(v) = &self.instruction {
+            config.instruction = Some(v.clone());
+        }
+        if let Some(v) = self.normalize_embeddings {
+            config.normalize_embeddings = v;
+        }
+        if let Some(v) = self.batch_size {
+            config.batch_size = v;
+        }
+        if let Some(v) = self.workers {
+            config.workers = v;
+        }
+        if let Some(v) = self.queue_capacity {
+            config.queue_capacity = v;
+        }
+        if let Some(v) = self.queue_policy {
+            config.queue_policy = v;
+        }
+        if let Some(v) = self.queue_timeout_ms {
+            config.queue_timeout_ms = v;
+        }
+        if let Some(v) = self.compute_threads {
+            config.compute_threads = v;
+        }
+        if let Some(v) = self.batch_wait_ms {
+            config.batch_wait_ms = v;
+        }
+        if let Some(v) = self.max_batch_tokens {
+            config.max_batch_tokens = v;
+        }
+        if let Some(v) = self.truncation {
+            config.truncation = v;
+        }
+        if let Some(v) = self.chunk_overlap {
+            config.chunk_overlap = v;
+        }
+        if let Some(v) = &self.prompts {
+            config.prompts = v.clone();
+        }
+        if let Some(v) = &self.default_prompt_name {
+            config.default_prompt_name = Some(v.clone());
+        }
+        config
+    }
+}
+
+fn default_compute_threads() -> usize {
+    1
+}
+
+fn default_retry_after_secs() -> u64 {
+    1
+}
+
+fn default_batch_wait_ms() -> u64 {
+    5
+}
+
+fn default_max_batch_tokens() -> usize {
+    16384
+}
+
+fn default_chunk_overlap() -> usize {
+    64
 }
 
 impl Config {
+    /// 展开为每个模型各自的完整配置，第一个为默认模型
+    ///
+    /// 未配置 models 时只有顶层配置描述的一个模型。
+    pub fn model_configs(&self) -> Vec<Config> {
+        if self.models.is_empty() {
+            return vec![self.clone()];
+        }
+        self.models.iter().map(|entry| entry.apply(self)).collect()
+    }
+
     pub fn from_env_or_file() -> Self {
         let mut config = Self::from_file().unwrap_or_else(Self::from_defaults);
         config.apply_env_overrides();
@@ -64,7 +277,73 @@ impl Config {
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(100);
+        let queue_policy = env::var("QUEUE_POLICY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default();
+        let queue_timeout_ms = env::var("QUEUE_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let retry_after_secs = env::var("RETRY_AFTER_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(default_retry_after_secs);
         let model_name = env::var("MODEL_NAME").unwrap_or_else(|_| "yuan-embedding-2.0-zh".to_string());
+        let pooling = env::var("POOLING").ok().and_then(|v| v.parse().ok());
+        let instruction = env::var("INSTRUCTION").ok();
+        let dtype = env::var("DTYPE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default();
+        let compute_threads = env::var("COMPUTE_THREADS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(default_compute_threads);
+        let intra_op_threads = env::var("INTRA_OP_THREADS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let batch_wait_ms = env::var("BATCH_WAIT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(default_batch_wait_ms);
+        let max_batch_tokens = env::var("MAX_BATCH_TOKENS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(default_max_batch_tokens);
+        let tokenizer_path = env::var("TOKENIZER_PATH").ok();
+        let backend_timeout_ms = env::var("BACKEND_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let truncation = env::var("TRUNCATION")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default();
+        let chunk_overlap = env::var("CHUNK_OVERLAP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(default_chunk_overlap);
+        // PROMPTS 为 JSON 对象，如 {"query": "query: ", "passage": "passage: "}
+        let prompts = env::var("PROMPTS")
+            .ok()
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default();
+        let default_prompt_name = env::var("DEFAULT_PROMPT_NAME").ok();
+        let tei_compat = env::var("TEI_COMPAT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false);
+        let tei_prefix = env::var("TEI_PREFIX").unwrap_or_default();
+        let model_aliases = env::var("MODEL_ALIASES")
+            .map(|v| parse_list(&v))
+            .unwrap_or_default();
+        // MODELS 为 JSON 数组，每项字段与 config.toml 中的 [[models]] 相同
+        let models = env::var("MODELS")
+            .ok()
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default();
 
         Self {
             host,
@@ -76,7 +355,27 @@ impl Config {
             batch_size,
             workers,
             queue_capacity,
+            queue_policy,
+            queue_timeout_ms,
+            retry_after_secs,
             model_name,
+            pooling,
+            instruction,
+            dtype,
+            compute_threads,
+            intra_op_threads,
+            batch_wait_ms,
+            max_batch_tokens,
+            tokenizer_path,
+            backend_timeout_ms,
+            truncation,
+            chunk_overlap,
+            prompts,
+            default_prompt_name,
+            tei_compat,
+            tei_prefix,
+            model_aliases,
+            models,
         }
     }
 
@@ -120,8 +419,108 @@ impl Config {
                 self.queue_capacity = v;
             }
         }
+        if let Ok(value) = env::var("QUEUE_POLICY") {
+            if let Ok(v) = value.parse() {
+                self.queue_policy = v;
+            }
+        }
+        if let Ok(value) = env::var("QUEUE_TIMEOUT_MS") {
+            if let Ok(v) = value.parse() {
+                self.queue_timeout_ms = v;
+            }
+        }
+        if let Ok(value) = env::var("RETRY_AFTER_SECS") {
+            if let Ok(v) = value.parse() {
+                self.retry_after_secs = v;
+            }
+        }
         if let Ok(value) = env::var("MODEL_NAME") {
             self.model_name = value;
         }
+        if let Ok(value) = env::var("POOLING") {
+            if let Ok(v) = value.parse() {
+                self.pooling = Some(v);
+            }
+        }
+        if let Ok(value) = env::var("INSTRUCTION") {
+            self.instruction = Some(value);
+        }
+        if let Ok(value) = env::var("DTYPE") {
+            if let Ok(v) = value.parse() {
+                self.dtype = v;
+            }
+        }
+        if let Ok(value) = env::var("COMPUTE_THREADS") {
+            if let Ok(v) = value.parse() {
+                self.compute_threads = v;
+            }
+        }
+        if let Ok(value) = env::var("INTRA_OP_THREADS") {
+            if let Ok(v) = value.parse() {
+                self.intra_op_threads = v;
+            }
+        }
+        if let Ok(value) = env::var("BATCH_WAIT_MS") {
+            if let Ok(v) = value.parse() {
+                self.batch_wait_ms = v;
+            }
+        }
+        if let Ok(value) = env::var("MAX_BATCH_TOKENS") {
+            if let Ok(v) = value.parse() {
+                self.max_batch_tokens = v;
+            }
+        }
+        if let Ok(value) = env::var("TOKENIZER_PATH") {
+            self.tokenizer_path = Some(value);
+        }
+        if let Ok(value) = env::var("BACKEND_TIMEOUT_MS") {
+            if let Ok(v) = value.parse() {
+                self.backend_timeout_ms = v;
+            }
+        }
+        if let Ok(value) = env::var("TRUNCATION") {
+            if let Ok(v) = value.parse() {
+                self.truncation = v;
+            }
+        }
+        if let Ok(value) = env::var("CHUNK_OVERLAP") {
+            if let Ok(v) = value.parse() {
+                self.chunk_overlap = v;
+            }
+        }
+        if let Ok(value) = env::var("PROMPTS") {
+            if let Ok(v) = serde_json::from_str(&value) {
+                self.prompts = v;
+            }
+        }
+        if let Ok(value) = env::var("DEFAULT_PROMPT_NAME") {
+            self.default_prompt_name = Some(value);
+        }
+        if let Ok(value) = env::var("TEI_COMPAT") {
+            if let Ok(v) = value.parse() {
+                self.tei_compat = v;
+            }
+        }
+        if let Ok(value) = env::var("TEI_PREFIX") {
+            self.tei_prefix = value;
+        }
+        if let Ok(value) = env::var("MODEL_ALIASES") {
+            self.model_aliases = parse_list(&value);
+        }
+        if let Ok(value) = env::var("MODELS") {
+            if let Ok(v) = serde_json::from_str(&value) {
+                self.models = v;
+            }
+        }
     }
 }
+
+// 逗号分隔的列表，忽略空项
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
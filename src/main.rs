@@ -1,52 +1,247 @@
 mod backend;
 mod config;
 mod queue;
+mod registry;
+mod tei;
 mod types;
 
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{rejection::JsonRejection, FromRequest, Path, Request, State},
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use base64::Engine;
 use serde_json::json;
 use thiserror::Error;
 use tracing::info;
 
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::backend::{BackendClient, BackendType, EmbeddingBackend};
+use crate::backend::prompts::Prompts;
+use crate::backend::{BackendClient, BackendError, BackendType, EmbeddingBackend};
 use crate::config::Config;
-use crate::queue::Queue;
+use crate::queue::{normalize, Admission, Queue};
+use crate::registry::{ModelRegistry, ServedModel};
 use crate::types::{
-    EmbedRequest, EmbedResponse, EmbeddingData, OpenAIEmbeddingsRequest,
-    OpenAIEmbeddingsResponse, Usage,
+    DetokenizeData, DetokenizeRequest, DetokenizeResponse, EmbedRequest, EmbedResponse, Embedding,
+    EmbeddingData, ModelList, ModelObject, OpenAIEmbeddingsRequest, OpenAIEmbeddingsResponse,
+    RerankRequest, RerankResponse, RerankResult, RerankResultDocument, RerankUsage, TokenizeData,
+    TokenizeRequest, TokenizeResponse, Usage,
 };
 
 #[derive(Clone)]
 struct AppState {
-    queue: Queue,
-    config: Config,
+    models: Arc<ModelRegistry>,
+}
+
+impl AppState {
+    fn model(&self, name: Option<&str>) -> Result<Arc<ServedModel>, AppError> {
+        self.models
+            .get(name)
+            .ok_or_else(|| AppError::ModelNotFound(name.unwrap_or_default().to_string()))
+    }
 }
 
 #[derive(Debug, Error)]
 enum AppError {
     #[error("bad request: {0}")]
     BadRequest(String),
+    #[error("invalid request body: {0}")]
+    InvalidBody(JsonRejection),
+    #[error("model not found: {0}")]
+    ModelNotFound(String),
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    #[error("timeout: {0}")]
+    Timeout(String),
+    /// 本服务的队列过载，响应带 Retry-After 和队列状态
+    #[error("overloaded: {message}")]
+    Overloaded {
+        status: StatusCode,
+        code: &'static str,
+        message: String,
+        retry_after: u64,
+        queue_depth: usize,
+        queue_capacity: usize,
+    },
     #[error("backend error: {0}")]
     Backend(String),
+    #[error("internal error: {0}")]
+    Internal(String),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::Backend(msg) => (StatusCode::BAD_GATEWAY, msg),
+        // 与 OpenAI 一致的错误格式：{"error": {"message", "type", "param", "code"}}
+        let mut overload = None;
+        let (status, error_type, code, param, message) = match self {
+            AppError::BadRequest(msg) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "invalid_input",
+                None,
+                msg,
+            ),
+            AppError::InvalidBody(rejection) => (
+                // 缺少 Content-Type 时保留 415，JSON 语法或字段错误与 OpenAI 一致返回 400
+                match rejection {
+                    JsonRejection::MissingJsonContentType(_) => rejection.status(),
+                    _ => StatusCode::BAD_REQUEST,
+                },
+                "invalid_request_error",
+                "invalid_json",
+                None,
+                rejection.body_text(),
+            ),
+            AppError::ModelNotFound(model) => (
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                "model_not_found",
+                Some("model"),
+                format!("The model `{}` does not exist", model),
+            ),
+            AppError::RateLimited(msg) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                "rate_limit_exceeded",
+                None,
+                msg,
+            ),
+            AppError::Unavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_error",
+                "service_unavailable",
+                None,
+                msg,
+            ),
+            AppError::Timeout(msg) => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "server_error",
+                "timeout",
+                None,
+                msg,
+            ),
+            AppError::Overloaded {
+                status,
+                code,
+                message,
+                retry_after,
+                queue_depth,
+                queue_capacity,
+            } => {
+                overload = Some((retry_after, queue_depth, queue_capacity));
+                let error_type = if status == StatusCode::TOO_MANY_REQUESTS {
+                    "rate_limit_error"
+                } else {
+                    "server_error"
+                };
+                (status, error_type, code, None, message)
+            }
+            AppError::Backend(msg) => (
+                StatusCode::BAD_GATEWAY,
+                "server_error",
+                "backend_error",
+                None,
+                msg,
+            ),
+            AppError::Internal(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "internal_error",
+                None,
+                msg,
+            ),
         };
 
-        let body = Json(json!({ "error": message }));
-        (status, body).into_response()
+        let body = Json(json!({
+            "error": {
+                "message": message,
+                "type": error_type,
+                "param": param,
+                "code": code,
+            }
+        }));
+        let mut response = (status, body).into_response();
+        // 过载响应同样带上队列状态，便于客户端退避
+        if let Some((retry_after, queue_depth, queue_capacity)) = overload {
+            let headers = response.headers_mut();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            headers.insert("x-queue-depth", HeaderValue::from(queue_depth));
+            headers.insert("x-queue-capacity", HeaderValue::from(queue_capacity));
+        }
+        response
+    }
+}
+
+impl From<BackendError> for AppError {
+    fn from(e: BackendError) -> Self {
+        match e {
+            BackendError::InvalidInput(msg) => AppError::BadRequest(msg),
+            BackendError::Inference(_) => AppError::Internal(e.to_string()),
+            BackendError::Timeout(_) => AppError::Timeout(e.to_string()),
+            BackendError::Unavailable(_) => AppError::Unavailable(e.to_string()),
+            BackendError::QueueFull {
+                depth,
+                capacity,
+                retry_after,
+            } => AppError::Overloaded {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                code: "queue_full",
+                message: e.to_string(),
+                retry_after,
+                queue_depth: depth,
+                queue_capacity: capacity,
+            },
+            BackendError::Overloaded {
+                depth,
+                capacity,
+                retry_after,
+                ..
+            } => AppError::Overloaded {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                code: "overloaded",
+                message: e.to_string(),
+                retry_after,
+                queue_depth: depth,
+                queue_capacity: capacity,
+            },
+            // 上游的限流、过载和超时原样传递给客户端，其余状态码视为网关错误
+            BackendError::Status(status) => match status.as_u16() {
+                429 => AppError::RateLimited(e.to_string()),
+                503 => AppError::Unavailable(e.to_string()),
+                408 | 504 => AppError::Timeout(e.to_string()),
+                _ => AppError::Backend(e.to_string()),
+            },
+            e => AppError::Backend(e.to_string()),
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::InvalidBody(rejection)
+    }
+}
+
+/// 与 axum 的 Json 相同，请求体解析失败时返回 OpenAI 格式的错误
+struct AppJson<T>(T);
+
+#[axum::async_trait]
+impl<S, T> FromRequest<S> for AppJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(AppJson(value))
     }
 }
 
@@ -57,32 +252,73 @@ async fn main() {
         .init();
 
     let config = Config::from_env_or_file();
+    // 算子线程数对整个进程生效，在加载模型前设置一次
+    crate::backend::candle::init_intra_op_threads(config.intra_op_threads)
+        .expect("Failed to configure intra-op threads");
     
-    // 根据配置选择后端
-    let backend: Arc<dyn EmbeddingBackend> = match config.backend_type {
-        BackendType::Proxy => {
-            info!("Using proxy backend: {}", config.backend_url);
-            Arc::new(BackendClient::new(config.backend_url.clone()))
-        }
-        BackendType::Candle => {
-            info!("Using candle backend: {}", config.model_path);
-            let candle_backend = crate::backend::candle::CandleBackend::new(config.model_path.clone())
-                .expect("Failed to create candle backend");
-            Arc::new(candle_backend)
-        }
-    };
-    
-    let queue = Queue::new(backend, config.workers, config.queue_capacity);
+    let mut models = ModelRegistry::default();
+    for model_config in config.model_configs() {
+        let backend = build_backend(&model_config);
+        let model_info = backend.model_info();
+        let queue = Queue::new(
+            backend.clone(),
+            model_config.workers,
+            model_config.queue_capacity,
+            model_config.batch_size as usize,
+            Duration::from_millis(model_config.batch_wait_ms),
+            Admission {
+                policy: model_config.queue_policy,
+                max_wait: (model_config.queue_timeout_ms > 0)
+                    .then(|| Duration::from_millis(model_config.queue_timeout_ms)),
+                retry_after: model_config.retry_after_secs,
+            },
+        );
+        info!("Serving model: {}", model_config.model_name);
+        let aliases = model_config.model_aliases.clone();
+        let model = ServedModel {
+            name: model_config.model_name.clone(),
+            queue,
+            backend,
+            config: model_config,
+            model_info,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        models.register(model, &aliases).expect("Invalid model registry");
+    }
 
     let host = config.host.clone();
     let port = config.port;
-    let state = AppState { queue, config };
+    let state = AppState {
+        models: Arc::new(models),
+    };
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/health", get(health))
-        .route("/embed", post(embed_compat))
         .route("/v1/embeddings", post(openai_embeddings))
-        .with_state(state);
+        .route("/v1/rerank", post(rerank))
+        .route("/v1/models", get(list_models))
+        .route("/v1/models/*id", get(retrieve_model))
+        .route("/detokenize", post(detokenize));
+    // TEI 接口挂在根路径时，/embed 与 /tokenize 使用 TEI 的请求格式
+    let tei_prefix = config.tei_prefix.trim_matches('/');
+    if config.tei_compat {
+        info!("TEI compatible API enabled at /{}", tei_prefix);
+        app = if tei_prefix.is_empty() {
+            app.merge(tei::router())
+        } else {
+            app.route("/embed", post(embed_compat))
+                .route("/tokenize", post(tokenize))
+                .nest(&format!("/{}", tei_prefix), tei::router())
+        };
+    } else {
+        app = app
+            .route("/embed", post(embed_compat))
+            .route("/tokenize", post(tokenize));
+    }
+    let app = app.with_state(state);
 
     let addr = format!("{}:{}", host, port);
     info!("LLM.rs listening on http://{}", addr);
@@ -96,73 +332,347 @@ async fn main() {
         .expect("server error");
 }
 
+// 根据配置选择后端
+fn build_backend(config: &Config) -> Arc<dyn EmbeddingBackend> {
+    let options = crate::backend::candle::CandleOptions {
+        pooling: config.pooling,
+        dtype: config.dtype,
+        instruction: config.instruction.clone(),
+        prompts: Prompts::new(config.prompts.clone(), config.default_prompt_name.clone()),
+        compute_threads: config.compute_threads,
+        max_batch_tokens: config.max_batch_tokens,
+        chunk_overlap: config.chunk_overlap,
+    };
+    match config.backend_type {
+        BackendType::Proxy => {
+            info!("Using proxy backend: {}", config.backend_url);
+            let tokenizer = config.tokenizer_path.as_ref().map(|path| {
+                tokenizers::Tokenizer::from_file(path).expect("Failed to load tokenizer")
+            });
+            let timeout = (config.backend_timeout_ms > 0)
+                .then(|| Duration::from_millis(config.backend_timeout_ms));
+            Arc::new(BackendClient::new(
+                config.backend_url.clone(),
+                tokenizer,
+                options.prompts.clone(),
+                timeout,
+            ))
+        }
+        BackendType::Candle => {
+            if crate::backend::candle::is_cross_encoder(&config.model_path) {
+                info!("Using cross-encoder backend: {}", config.model_path);
+                let rerank_backend = crate::backend::candle::CrossEncoderBackend::new(config.model_path.clone(), options)
+                    .expect("Failed to create cross-encoder backend");
+                return Arc::new(rerank_backend);
+            }
+            info!("Using candle backend: {}", config.model_path);
+            let candle_backend = crate::backend::candle::CandleBackend::new(config.model_path.clone(), options)
+                .expect("Failed to create candle backend");
+            Arc::new(candle_backend)
+        }
+        BackendType::Gguf => {
+            info!("Using GGUF backend: {}", config.model_path);
+            let gguf_backend = crate::backend::candle::CandleBackend::from_gguf(config.model_path.clone(), options)
+                .expect("Failed to create GGUF backend");
+            Arc::new(gguf_backend)
+        }
+    }
+}
+
 async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    // 顶层字段描述默认模型
+    let default = &state.models.models()[0];
+    let models: Vec<&str> = state.models.models().iter().map(|m| m.name.as_str()).collect();
     Json(json!({
         "status": "ok",
-        "backend_url": state.config.backend_url,
-        "model_name": state.config.model_name,
+        "backend_url": default.config.backend_url,
+        "model_name": default.name,
+        "quantization": default.model_info.quantization,
+        "models": models,
     }))
 }
 
+async fn list_models(State(state): State<AppState>) -> Json<ModelList> {
+    let data = state.models.models().iter().map(|m| model_object(m)).collect();
+    Json(ModelList {
+        object: "list".to_string(),
+        data,
+    })
+}
+
+// 按模型名或别名查询，返回模型的正式名称；id 可能包含斜杠（如 BAAI/bge-m3），路由使用通配符匹配
+async fn retrieve_model(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ModelObject>, AppError> {
+    let model = state.model(Some(&id))?;
+    Ok(Json(model_object(&model)))
+}
+
+fn model_object(model: &ServedModel) -> ModelObject {
+    ModelObject {
+        id: model.name.clone(),
+        object: "model".to_string(),
+        created: model.created,
+        owned_by: "llmrs".to_string(),
+        backend_type: model.config.backend_type.as_str().to_string(),
+        aliases: model.config.model_aliases.clone(),
+        info: model.model_info.clone(),
+    }
+}
+
 async fn openai_embeddings(
     State(state): State<AppState>,
-    Json(payload): Json<OpenAIEmbeddingsRequest>,
-) -> Result<Json<OpenAIEmbeddingsResponse>, AppError> {
-    let texts = payload.input.into_vec();
-    if texts.is_empty() {
+    AppJson(payload): AppJson<OpenAIEmbeddingsRequest>,
+) -> Result<(QueueHeaders, Json<OpenAIEmbeddingsResponse>), AppError> {
+    let model = state.model(payload.model.as_deref())?;
+    let inputs = payload.input.into_vec();
+    if inputs.is_empty() {
         return Err(AppError::BadRequest("input cannot be empty".to_string()));
     }
 
-    if let Some(format) = payload.encoding_format.as_deref() {
-        if format != "float" {
-            return Err(AppError::BadRequest(
-                "only encoding_format=float is supported".to_string(),
-            ));
+    let base64 = match payload.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "unsupported encoding_format: {}, expected float or base64",
+                other
+            )))
         }
-    }
+    };
+
+    check_dimensions(model.model_info.dimension, payload.dimensions)?;
 
-    let response = state
+    let mut response = model
         .queue
         .enqueue(
-            texts,
-            state.config.normalize_embeddings,
-            state.config.batch_size,
+            inputs,
+            model.config.normalize_embeddings,
+            model.config.batch_size,
+            payload.truncation.unwrap_or(model.config.truncation),
+            payload.prompt_name.or(payload.input_type),
         )
-        .await
-        .map_err(|e| AppError::Backend(e.to_string()))?;
+        .await?;
 
-    Ok(Json(map_openai_response(
-        payload.model.unwrap_or_else(|| state.config.model_name.clone()),
-        response,
-    )))
+    if let Some(dimensions) = payload.dimensions {
+        truncate_dimensions(&mut response, dimensions, model.config.normalize_embeddings)?;
+    }
+
+    Ok((
+        queue_headers(&model.queue),
+        Json(map_openai_response(
+            payload.model.unwrap_or_else(|| model.name.clone()),
+            response,
+            base64,
+        )),
+    ))
 }
 
 async fn embed_compat(
     State(state): State<AppState>,
-    Json(payload): Json<EmbedRequest>,
-) -> Result<Json<EmbedResponse>, AppError> {
-    let texts = payload.texts.into_vec();
-    if texts.is_empty() {
+    AppJson(payload): AppJson<EmbedRequest>,
+) -> Result<(QueueHeaders, Json<EmbedResponse>), AppError> {
+    let model = state.model(payload.model.as_deref())?;
+    let inputs = payload.texts.into_vec();
+    if inputs.is_empty() {
         return Err(AppError::BadRequest("texts cannot be empty".to_string()));
     }
 
-    let response = state
+    let response = model
         .queue
-        .enqueue(texts, payload.normalize_embeddings, payload.batch_size)
-        .await
-        .map_err(|e| AppError::Backend(e.to_string()))?;
+        .enqueue(
+            inputs,
+            payload.normalize_embeddings,
+            payload.batch_size,
+            payload.truncation.unwrap_or(model.config.truncation),
+            payload.prompt_name.or(payload.input_type),
+        )
+        .await?;
 
-    Ok(Json(response))
+    Ok((queue_headers(&model.queue), Json(response)))
 }
 
-fn map_openai_response(model: String, embed: EmbedResponse) -> OpenAIEmbeddingsResponse {
+// 与 Cohere / Jina 的 rerank 接口兼容，结果按相关性分数从高到低排列
+async fn rerank(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<RerankRequest>,
+) -> Result<(QueueHeaders, Json<RerankResponse>), AppError> {
+    let model = state.model(payload.model.as_deref())?;
+    if payload.documents.is_empty() {
+        return Err(AppError::BadRequest("documents cannot be empty".to_string()));
+    }
+    if payload.top_n == Some(0) {
+        return Err(AppError::BadRequest("top_n must be greater than 0".to_string()));
+    }
+
+    let documents: Vec<String> = payload
+        .documents
+        .into_iter()
+        .map(|document| document.into_text())
+        .collect();
+    let scores = model
+        .queue
+        .enqueue_rerank(payload.query, documents.clone(), model.config.batch_size)
+        .await?;
+
+    let mut results: Vec<RerankResult> = scores
+        .scores
+        .iter()
+        .enumerate()
+        .map(|(index, &relevance_score)| RerankResult {
+            index,
+            relevance_score,
+            document: payload.return_documents.then(|| RerankResultDocument {
+                text: documents[index].clone(),
+            }),
+        })
+        .collect();
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    if let Some(top_n) = payload.top_n {
+        results.truncate(top_n);
+    }
+
+    let response = RerankResponse {
+        model: payload.model.unwrap_or_else(|| model.name.clone()),
+        results,
+        usage: RerankUsage {
+            total_tokens: scores.token_counts.iter().sum::<usize>() as u32,
+        },
+    };
+    Ok((queue_headers(&model.queue), Json(response)))
+}
+
+// 用模型的 tokenizer 分词，提示词前缀与嵌入接口一致，便于提前统计 token 数和截断
+async fn tokenize(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TokenizeRequest>,
+) -> Result<Json<TokenizeResponse>, AppError> {
+    let model = state.model(payload.model.as_deref())?;
+    let inputs = payload.input.into_vec();
+    if inputs.is_empty() {
+        return Err(AppError::BadRequest("input cannot be empty".to_string()));
+    }
+
+    let prompt_name = payload.prompt_name.or(payload.input_type);
+    let tokenized = model
+        .backend
+        .tokenize(inputs, payload.add_special_tokens, prompt_name.as_deref())?;
+    let data = tokenized
+        .into_iter()
+        .enumerate()
+        .map(|(index, t)| TokenizeData {
+            index,
+            count: t.tokens.len(),
+            ids: t.tokens.iter().map(|token| token.id).collect(),
+            offsets: t.tokens.iter().map(|token| token.offsets).collect(),
+            special: t.tokens.iter().map(|token| token.special).collect(),
+            prefix: t.tokens.iter().map(|token| token.prefix).collect(),
+            tokens: t.tokens.into_iter().map(|token| token.token).collect(),
+            truncated_tokens: t.truncated_tokens,
+        })
+        .collect();
+
+    Ok(Json(TokenizeResponse {
+        model: payload.model.unwrap_or_else(|| model.name.clone()),
+        max_seq_len: model.model_info.max_seq_len,
+        data,
+    }))
+}
+
+async fn detokenize(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<DetokenizeRequest>,
+) -> Result<Json<DetokenizeResponse>, AppError> {
+    let model = state.model(payload.model.as_deref())?;
+    let inputs = payload.input.into_vec();
+    if inputs.is_empty() {
+        return Err(AppError::BadRequest("input cannot be empty".to_string()));
+    }
+
+    let texts = model
+        .backend
+        .detokenize(inputs, payload.skip_special_tokens)?;
+    let data = texts
+        .into_iter()
+        .enumerate()
+        .map(|(index, text)| DetokenizeData { index, text })
+        .collect();
+
+    Ok(Json(DetokenizeResponse {
+        model: payload.model.unwrap_or_else(|| model.name.clone()),
+        data,
+    }))
+}
+
+/// 排队接口响应中附带的队列状态：当前排队的请求数和队列容量
+type QueueHeaders = [(&'static str, String); 2];
+
+fn queue_headers(queue: &Queue) -> QueueHeaders {
+    [
+        ("x-queue-depth", queue.depth().to_string()),
+        ("x-queue-capacity", queue.capacity().to_string()),
+    ]
+}
+
+// 在排队前校验 dimensions，避免无效请求占用一次前向计算；proxy 后端不知道维度，由 truncate_dimensions 兜底
+fn check_dimensions(
+    model_dimension: Option<usize>,
+    dimensions: Option<usize>,
+) -> Result<(), AppError> {
+    let Some(dimensions) = dimensions else {
+        return Ok(());
+    };
+    if dimensions == 0 {
+        return Err(AppError::BadRequest(
+            "dimensions must be greater than 0".to_string(),
+        ));
+    }
+    match model_dimension {
+        Some(dimension) if dimensions > dimension => Err(AppError::BadRequest(format!(
+            "dimensions {} exceeds the model dimension {}",
+            dimensions, dimension
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Matryoshka 截断：保留前 dimensions 维，开启归一化时截断后重新归一化
+fn truncate_dimensions(
+    response: &mut EmbedResponse,
+    dimensions: usize,
+    normalize_embeddings: bool,
+) -> Result<(), AppError> {
+    if dimensions > response.vector_dim {
+        return Err(AppError::BadRequest(format!(
+            "dimensions {} exceeds the model dimension {}",
+            dimensions, response.vector_dim
+        )));
+    }
+    for vector in &mut response.vectors {
+        vector.truncate(dimensions);
+        if normalize_embeddings {
+            normalize(vector);
+        }
+    }
+    response.vector_dim = dimensions;
+    Ok(())
+}
+
+fn map_openai_response(model: String, embed: EmbedResponse, base64: bool) -> OpenAIEmbeddingsResponse {
+    let prompt_tokens = embed.token_counts.iter().sum::<usize>() as u32;
+    let truncated = embed.truncated.iter().any(|&t| t);
     let data = embed
         .vectors
         .into_iter()
         .enumerate()
         .map(|(index, embedding)| EmbeddingData {
             object: "embedding".to_string(),
-            embedding,
+            embedding: if base64 {
+                Embedding::Base64(encode_base64(&embedding))
+            } else {
+                Embedding::Float(embedding)
+            },
             index,
         })
         .collect();
@@ -172,8 +682,138 @@ fn map_openai_response(model: String, embed: EmbedResponse) -> OpenAIEmbeddingsR
         data,
         model,
         usage: Usage {
-            prompt_tokens: 0,
-            total_tokens: 0,
+            prompt_tokens,
+            total_tokens: prompt_tokens,
         },
+        truncated,
+    }
+}
+
+// 与 OpenAI 一致：小端 f32 字节序列做标准 base64 编码
+fn encode_base64(embedding: &[f32]) -> String {
+    let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(vectors: Vec<Vec<f32>>) -> EmbedResponse {
+        EmbedResponse {
+            count: vectors.len(),
+            vector_dim: vectors.first().map_or(0, Vec::len),
+            model_path: "test".to_string(),
+            token_counts: Vec::new(),
+            truncated: Vec::new(),
+            vectors,
+        }
+    }
+
+    fn status(e: BackendError) -> StatusCode {
+        AppError::from(e).into_response().status()
+    }
+
+    #[test]
+    fn backend_errors_map_to_matching_statuses() {
+        let upstream = |code| BackendError::Status(reqwest::StatusCode::from_u16(code).unwrap());
+        let cases = [
+            (BackendError::InvalidInput("bad".to_string()), StatusCode::BAD_REQUEST),
+            (BackendError::Inference("nan".to_string()), StatusCode::INTERNAL_SERVER_ERROR),
+            (BackendError::Timeout("slow".to_string()), StatusCode::GATEWAY_TIMEOUT),
+            (BackendError::Unavailable("down".to_string()), StatusCode::SERVICE_UNAVAILABLE),
+            (
+                BackendError::QueueFull {
+                    depth: 1,
+                    capacity: 1,
+                    retry_after: 1,
+                },
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            (
+                BackendError::Overloaded {
+                    reason: "shed".to_string(),
+                    depth: 1,
+                    capacity: 1,
+                    retry_after: 1,
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (upstream(429), StatusCode::TOO_MANY_REQUESTS),
+            (upstream(503), StatusCode::SERVICE_UNAVAILABLE),
+            (upstream(408), StatusCode::GATEWAY_TIMEOUT),
+            (upstream(504), StatusCode::GATEWAY_TIMEOUT),
+            (upstream(500), StatusCode::BAD_GATEWAY),
+            (upstream(404), StatusCode::BAD_GATEWAY),
+            (BackendError::Request("refused".to_string()), StatusCode::BAD_GATEWAY),
+            (BackendError::Decode("garbage".to_string()), StatusCode::BAD_GATEWAY),
+        ];
+        for (error, expected) in cases {
+            let message = error.to_string();
+            assert_eq!(status(error), expected, "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn app_json_keeps_415_for_a_missing_content_type() {
+        let request = axum::http::Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .body(axum::body::Body::from(r#"{"input": "hello"}"#))
+            .unwrap();
+        let Err(rejection) = AppJson::<serde_json::Value>::from_request(request, &()).await else {
+            panic!("request without a content type was accepted");
+        };
+        assert_eq!(
+            rejection.into_response().status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[test]
+    fn encode_base64_round_trips_little_endian_f32() {
+        let embedding = vec![0.5f32, -1.25, f32::MIN_POSITIVE, 3.0e8];
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encode_base64(&embedding))
+            .unwrap();
+        let decoded: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(decoded, embedding);
+    }
+
+    #[test]
+    fn check_dimensions_rejects_zero_and_oversized_values() {
+        assert!(check_dimensions(Some(4), None).is_ok());
+        assert!(check_dimensions(Some(4), Some(4)).is_ok());
+        assert!(matches!(
+            check_dimensions(Some(4), Some(0)),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            check_dimensions(Some(4), Some(5)),
+            Err(AppError::BadRequest(_))
+        ));
+        // proxy 后端不知道模型维度，只检查 0
+        assert!(check_dimensions(None, Some(1024)).is_ok());
+    }
+
+    #[test]
+    fn truncate_dimensions_renormalizes_only_when_normalizing() {
+        let mut normalized = response(vec![vec![3.0, 4.0, 12.0]]);
+        truncate_dimensions(&mut normalized, 2, true).unwrap();
+        assert_eq!(normalized.vectors, vec![vec![0.6, 0.8]]);
+        assert_eq!(normalized.vector_dim, 2);
+
+        let mut raw = response(vec![vec![3.0, 4.0, 12.0]]);
+        truncate_dimensions(&mut raw, 2, false).unwrap();
+        assert_eq!(raw.vectors, vec![vec![3.0, 4.0]]);
+
+        let mut oversized = response(vec![vec![3.0, 4.0, 12.0]]);
+        assert!(matches!(
+            truncate_dimensions(&mut oversized, 4, true),
+            Err(AppError::BadRequest(_))
+        ));
     }
 }
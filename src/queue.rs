@@ -1,77 +1,750 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::sync::{mpsc, oneshot, Mutex};
-use tracing::error;
+use tokio::sync::{oneshot, Mutex, Notify};
+use tokio::time::Instant;
+use tracing::{error, warn};
 
 use crate::backend::{BackendError, EmbeddingBackend};
-use crate::types::EmbedResponse;
+use crate::config::QueuePolicy;
+use crate::types::{EmbedInput, EmbedResponse, RerankScores, Truncation};
 
 #[derive(Clone)]
 pub struct Queue {
-    sender: mpsc::Sender<EmbedJob>,
+    shared: Arc<Shared>,
+}
+
+/// 队列满时的准入设置
+#[derive(Debug, Clone)]
+pub struct Admission {
+    pub policy: QueuePolicy,
+    /// wait 策略下等待入队的最长时间，None 表示一直等待
+    pub max_wait: Option<Duration>,
+    /// 拒绝请求时建议客户端重试的间隔（秒）
+    pub retry_after: u64,
+}
+
+struct Shared {
+    jobs: std::sync::Mutex<VecDeque<Job>>,
+    capacity: usize,
+    admission: Admission,
+    // 有任务入队时唤醒 worker
+    job_added: Notify,
+    // 有任务被取走时唤醒等待入队的请求
+    space_freed: Notify,
+    // 合并期间持有，其他 worker 等本批凑齐后再取下一批
+    collecting: Mutex<()>,
+}
+
+/// 队列中的任务：嵌入任务可以合并成一批，重排序和 token 向量任务单独执行
+pub enum Job {
+    Embed(EmbedJob),
+    Rerank(RerankJob),
+    EmbedAll(EmbedAllJob),
 }
 
 pub struct EmbedJob {
-    pub texts: Vec<String>,
+    pub inputs: Vec<EmbedInput>,
     pub normalize_embeddings: bool,
     pub batch_size: u32,
+    pub truncation: Truncation,
+    pub prompt_name: Option<String>,
     pub response: oneshot::Sender<Result<EmbedResponse, BackendError>>,
 }
 
+pub struct RerankJob {
+    pub query: String,
+    pub documents: Vec<String>,
+    pub batch_size: u32,
+    pub response: oneshot::Sender<Result<RerankScores, BackendError>>,
+}
+
+pub struct EmbedAllJob {
+    pub inputs: Vec<EmbedInput>,
+    pub batch_size: u32,
+    pub truncation: Truncation,
+    pub prompt_name: Option<String>,
+    pub response: oneshot::Sender<Result<Vec<Vec<Vec<f32>>>, BackendError>>,
+}
+
+impl Job {
+    // 计入合并批次大小的文本数
+    fn len(&self) -> usize {
+        match self {
+            Job::Embed(job) => job.inputs.len(),
+            Job::Rerank(job) => job.documents.len(),
+            Job::EmbedAll(job) => job.inputs.len(),
+        }
+    }
+
+    // 不执行任务，直接返回错误
+    fn fail(self, e: BackendError) {
+        let sent = match self {
+            Job::Embed(job) => job.response.send(Err(e)).is_ok(),
+            Job::Rerank(job) => job.response.send(Err(e)).is_ok(),
+            Job::EmbedAll(job) => job.response.send(Err(e)).is_ok(),
+        };
+        if !sent {
+            error!("response channel dropped");
+        }
+    }
+}
+
+impl Shared {
+    // 取出队首任务，队列为空时等待
+    async fn recv(&self) -> Job {
+        loop {
+            let notified = self.job_added.notified();
+            let job = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+            if let Some(job) = job {
+                self.space_freed.notify_waiters();
+                return job;
+            }
+            notified.await;
+        }
+    }
+}
+
 impl Queue {
+    /// 创建队列并启动 worker
+    ///
+    /// worker 取到一个任务后，会在 max_wait 内继续合并队列中的其他任务，
+    /// 直到累计文本数达到 max_batch_size，然后只调用一次后端。
     pub fn new(
         backend: Arc<dyn EmbeddingBackend>,
         workers: usize,
         capacity: usize,
+        max_batch_size: usize,
+        max_wait: Duration,
+        admission: Admission,
     ) -> Self {
-        let (sender, receiver) = mpsc::channel::<EmbedJob>(capacity);
-        let receiver = Arc::new(Mutex::new(receiver));
+        let shared = Arc::new(Shared {
+            jobs: std::sync::Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            admission,
+            job_added: Notify::new(),
+            space_freed: Notify::new(),
+            collecting: Mutex::new(()),
+        });
 
         for _ in 0..workers.max(1) {
-            let rx = receiver.clone();
+            let shared = shared.clone();
             let backend = backend.clone();
             tokio::spawn(async move {
                 loop {
-                    let job = {
-                        let mut guard = rx.lock().await;
-                        guard.recv().await
+                    let jobs = {
+                        let _guard = shared.collecting.lock().await;
+                        collect_batch(&shared, max_batch_size, max_wait).await
                     };
 
-                    let Some(job) = job else { break };
-
-                    let result = backend
-                        .embed(job.texts, job.normalize_embeddings, job.batch_size)
-                        .await;
-                    if job.response.send(result).is_err() {
-                        error!("response channel dropped");
+                    // 每批在独立任务中执行，后端 panic 只影响本批请求，worker 继续取下一批
+                    let backend = backend.clone();
+                    let batch = tokio::spawn(async move { run_jobs(backend.as_ref(), jobs).await });
+                    if let Err(e) = batch.await {
+                        error!("embedding batch failed: {}", e);
                     }
                 }
             });
         }
 
-        Self { sender }
+        Self { shared }
+    }
+
+    /// 当前排队（尚未开始处理）的请求数
+    pub fn depth(&self) -> usize {
+        self.shared.jobs.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
     }
 
     pub async fn enqueue(
         &self,
-        texts: Vec<String>,
+        inputs: Vec<EmbedInput>,
         normalize_embeddings: bool,
         batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
     ) -> Result<EmbedResponse, BackendError> {
         let (tx, rx) = oneshot::channel();
         let job = EmbedJob {
-            texts,
+            inputs,
             normalize_embeddings,
             batch_size,
+            truncation,
+            prompt_name,
+            response: tx,
+        };
+        self.submit(Job::Embed(job), rx).await
+    }
+
+    pub async fn enqueue_rerank(
+        &self,
+        query: String,
+        documents: Vec<String>,
+        batch_size: u32,
+    ) -> Result<RerankScores, BackendError> {
+        let (tx, rx) = oneshot::channel();
+        let job = RerankJob {
+            query,
+            documents,
+            batch_size,
+            response: tx,
+        };
+        self.submit(Job::Rerank(job), rx).await
+    }
+
+    pub async fn enqueue_embed_all(
+        &self,
+        inputs: Vec<EmbedInput>,
+        batch_size: u32,
+        truncation: Truncation,
+        prompt_name: Option<String>,
+    ) -> Result<Vec<Vec<Vec<f32>>>, BackendError> {
+        let (tx, rx) = oneshot::channel();
+        let job = EmbedAllJob {
+            inputs,
+            batch_size,
+            truncation,
+            prompt_name,
             response: tx,
         };
+        self.submit(Job::EmbedAll(job), rx).await
+    }
+
+    async fn submit<T>(
+        &self,
+        job: Job,
+        rx: oneshot::Receiver<Result<T, BackendError>>,
+    ) -> Result<T, BackendError> {
+        self.admit(job).await?;
+
+        // worker 处理本批时 panic 会丢弃响应通道
+        rx.await.map_err(|_| {
+            BackendError::Inference("worker failed while processing the request".to_string())
+        })?
+    }
+
+    // 按准入策略将任务放入队列
+    async fn admit(&self, job: Job) -> Result<(), BackendError> {
+        let shared = &self.shared;
+        let admission = &shared.admission;
+        let deadline = admission.max_wait.map(|wait| Instant::now() + wait);
+        loop {
+            // 先注册等待再检查队列，避免错过检查之后的唤醒
+            let space_freed = shared.space_freed.notified();
+            tokio::pin!(space_freed);
+            space_freed.as_mut().enable();
+
+            {
+                let mut jobs = shared.jobs.lock().unwrap_or_else(|e| e.into_inner());
+                if jobs.len() < shared.capacity {
+                    jobs.push_back(job);
+                    drop(jobs);
+                    shared.job_added.notify_one();
+                    return Ok(());
+                }
+                match admission.policy {
+                    QueuePolicy::Reject => {
+                        return Err(BackendError::QueueFull {
+                            depth: jobs.len(),
+                            capacity: shared.capacity,
+                            retry_after: admission.retry_after,
+                        });
+                    }
+                    QueuePolicy::ShedOldest => {
+                        let oldest = jobs.pop_front();
+                        jobs.push_back(job);
+                        let depth = jobs.len();
+                        drop(jobs);
+                        shared.job_added.notify_one();
+                        if let Some(oldest) = oldest {
+                            warn!("queue is full, dropping the oldest queued request");
+                            oldest.fail(BackendError::Overloaded {
+                                reason: "request was dropped from the full queue".to_string(),
+                                depth,
+                                capacity: shared.capacity,
+                                retry_after: admission.retry_after,
+                            });
+                        }
+                        return Ok(());
+                    }
+                    QueuePolicy::Wait => {}
+                }
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, space_freed).await.is_err() {
+                        return Err(BackendError::Overloaded {
+                            reason: "timed out waiting for a queue slot".to_string(),
+                            depth: self.depth(),
+                            capacity: shared.capacity,
+                            retry_after: admission.retry_after,
+                        });
+                    }
+                }
+                None => space_freed.await,
+            }
+        }
+    }
+}
+
+// 等待第一个任务，再在截止时间前尽量合并后续任务
+async fn collect_batch(shared: &Shared, max_batch_size: usize, max_wait: Duration) -> Vec<Job> {
+    let first = shared.recv().await;
+    let mut total = first.len();
+    let mut jobs = vec![first];
+
+    let deadline = Instant::now() + max_wait;
+    while total < max_batch_size {
+        match tokio::time::timeout_at(deadline, shared.recv()).await {
+            Ok(job) => {
+                total += job.len();
+                jobs.push(job);
+            }
+            Err(_) => break,
+        }
+    }
+    jobs
+}
+
+async fn run_jobs(backend: &dyn EmbeddingBackend, jobs: Vec<Job>) {
+    let mut embed_jobs = Vec::new();
+    let mut rerank_jobs = Vec::new();
+    let mut embed_all_jobs = Vec::new();
+    for job in jobs {
+        match job {
+            Job::Embed(job) => embed_jobs.push(job),
+            Job::Rerank(job) => rerank_jobs.push(job),
+            Job::EmbedAll(job) => embed_all_jobs.push(job),
+        }
+    }
+    if !embed_jobs.is_empty() {
+        run_batch(backend, embed_jobs).await;
+    }
+    // 每个重排序任务的文档都和各自的 query 组成句对，逐个执行
+    for job in rerank_jobs {
+        let result = backend
+            .rerank(job.query, job.documents, job.batch_size)
+            .await;
+        if job.response.send(result).is_err() {
+            error!("response channel dropped");
+        }
+    }
+    for job in embed_all_jobs {
+        let result = backend
+            .embed_all(job.inputs, job.batch_size, job.truncation, job.prompt_name)
+            .await;
+        if job.response.send(result).is_err() {
+            error!("response channel dropped");
+        }
+    }
+}
+
+async fn run_batch(backend: &dyn EmbeddingBackend, jobs: Vec<EmbedJob>) {
+    // 截断方式或提示词不同的任务不能在一次调用中处理，按两者分组
+    let mut groups: Vec<Vec<EmbedJob>> = Vec::new();
+    for job in jobs {
+        let same_options = |group: &&mut Vec<EmbedJob>| {
+            group[0].truncation == job.truncation && group[0].prompt_name == job.prompt_name
+        };
+        match groups.iter_mut().find(same_options) {
+            Some(group) => group.push(job),
+            None => groups.push(vec![job]),
+        }
+    }
+    for group in groups {
+        run_merged(backend, group).await;
+    }
+}
+
+async fn run_merged(backend: &dyn EmbeddingBackend, mut jobs: Vec<EmbedJob>) {
+    if jobs.len() == 1 {
+        run_job(backend, jobs.remove(0)).await;
+        return;
+    }
+
+    // 输入不合法时后端会拒绝整批，逐个重试，只让出错的任务失败
+    let inputs: Vec<EmbedInput> = jobs.iter().flat_map(|job| job.inputs.clone()).collect();
+    let total = inputs.len();
+    let batch_size = jobs.iter().map(|job| job.batch_size).max().unwrap_or(1);
+    let truncation = jobs[0].truncation;
+    let prompt_name = jobs[0].prompt_name.clone();
+    let result = backend
+        .embed(inputs, false, batch_size, truncation, prompt_name)
+        .await;
+    if let Err(BackendError::InvalidInput(_)) = result {
+        for job in jobs {
+            run_job(backend, job).await;
+        }
+        return;
+    }
+
+    // 合并后的批次统一不归一化，按各任务自己的设置在拆分时处理
+    let counts: Vec<usize> = jobs.iter().map(|job| job.inputs.len()).collect();
+    let waiters: Vec<_> = jobs
+        .into_iter()
+        .map(|job| (job.normalize_embeddings, job.response))
+        .collect();
+
+    let result = result.and_then(|response| {
+        if response.vectors.len() == total {
+            Ok(response)
+        } else {
+            Err(BackendError::Decode(format!(
+                "expected {} vectors, got {}",
+                total,
+                response.vectors.len()
+            )))
+        }
+    });
+
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            for (_, tx) in waiters {
+                if tx.send(Err(e.clone())).is_err() {
+                    error!("response channel dropped");
+                }
+            }
+            return;
+        }
+    };
+
+    // 后端未返回 token 数或截断标记时各任务也留空
+    let has_token_counts = response.token_counts.len() == total;
+    let has_truncated = response.truncated.len() == total;
+    let mut vectors = response.vectors.into_iter();
+    let mut token_counts = response.token_counts.into_iter();
+    let mut truncated = response.truncated.into_iter();
+    for ((normalize_embeddings, tx), count) in waiters.into_iter().zip(counts) {
+        let mut job_vectors: Vec<Vec<f32>> = vectors.by_ref().take(count).collect();
+        let job_token_counts = if has_token_counts {
+            token_counts.by_ref().take(count).collect()
+        } else {
+            Vec::new()
+        };
+        let job_truncated = if has_truncated {
+            truncated.by_ref().take(count).collect()
+        } else {
+            Vec::new()
+        };
+        if normalize_embeddings {
+            job_vectors.iter_mut().for_each(|v| normalize(v));
+        }
+        let job_response = EmbedResponse {
+            vectors: job_vectors,
+            count,
+            vector_dim: response.vector_dim,
+            model_path: response.model_path.clone(),
+            token_counts: job_token_counts,
+            truncated: job_truncated,
+        };
+        if tx.send(Ok(job_response)).is_err() {
+            error!("response channel dropped");
+        }
+    }
+}
+
+async fn run_job(backend: &dyn EmbeddingBackend, job: EmbedJob) {
+    let result = backend
+        .embed(
+            job.inputs,
+            job.normalize_embeddings,
+            job.batch_size,
+            job.truncation,
+            job.prompt_name,
+        )
+        .await;
+    if job.response.send(result).is_err() {
+        error!("response channel dropped");
+    }
+}
+
+pub fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|&x| x * x).sum::<f32>().sqrt();
+    if norm > 1e-6 {
+        for x in vector {
+            *x /= norm;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use tokio::sync::Semaphore;
+
+    use crate::backend::ModelInfo;
+
+    // 每条文本返回 [3n, 4n]（n 为文本长度），文本为 "bad" 时整批返回 InvalidInput
+    struct StubBackend {
+        calls: std::sync::Mutex<Vec<usize>>,
+        gate: Semaphore,
+    }
+
+    impl StubBackend {
+        fn new() -> Arc<Self> {
+            Self::gated(Semaphore::MAX_PERMITS)
+        }
+
+        // 后端每次调用先取一个许可，许可用完后调用阻塞，用来把队列填满
+        fn gated(permits: usize) -> Arc<Self> {
+            Arc::new(Self {
+                calls: std::sync::Mutex::new(Vec::new()),
+                gate: Semaphore::new(permits),
+            })
+        }
+
+        // 每次调用的输入条数
+        fn calls(&self) -> Vec<usize> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        async fn wait_for_calls(&self, n: usize) {
+            while self.calls().len() < n {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingBackend for StubBackend {
+        async fn embed(
+            &self,
+            inputs: Vec<EmbedInput>,
+            normalize_embeddings: bool,
+            _batch_size: u32,
+            _truncation: Truncation,
+            _prompt_name: Option<String>,
+        ) -> Result<EmbedResponse, BackendError> {
+            self.calls.lock().unwrap().push(inputs.len());
+            self.gate.acquire().await.unwrap().forget();
+
+            let mut vectors = Vec::new();
+            for input in &inputs {
+                let EmbedInput::Text(text) = input else {
+                    unreachable!("stub only accepts text inputs")
+                };
+                if text == "bad" {
+                    return Err(BackendError::InvalidInput("bad input".to_string()));
+                }
+                let n = text.len() as f32;
+                let mut vector = vec![3.0 * n, 4.0 * n];
+                if normalize_embeddings {
+                    normalize(&mut vector);
+                }
+                vectors.push(vector);
+            }
+            Ok(EmbedResponse {
+                count: vectors.len(),
+                vector_dim: 2,
+                model_path: "stub".to_string(),
+                token_counts: inputs.iter().map(|_| 1).collect(),
+                truncated: vec![false; inputs.len()],
+                vectors,
+            })
+        }
+
+        fn model_info(&self) -> ModelInfo {
+            ModelInfo::default()
+        }
+    }
+
+    fn admission(policy: QueuePolicy, max_wait: Option<Duration>) -> Admission {
+        Admission {
+            policy,
+            max_wait,
+            retry_after: 7,
+        }
+    }
+
+    fn texts(texts: &[&str]) -> Vec<EmbedInput> {
+        texts.iter().map(|t| EmbedInput::Text(t.to_string())).collect()
+    }
+
+    fn embed(
+        queue: &Queue,
+        inputs: &[&str],
+        normalize_embeddings: bool,
+    ) -> tokio::task::JoinHandle<Result<EmbedResponse, BackendError>> {
+        let queue = queue.clone();
+        let inputs = texts(inputs);
+        tokio::spawn(async move {
+            queue
+                .enqueue(inputs, normalize_embeddings, 32, Truncation::Right, None)
+                .await
+        })
+    }
+
+    // 单 worker、每批只取一个任务，后端阻塞时第一个任务占住 worker，之后的任务留在队列中
+    fn blocked_queue(policy: QueuePolicy, max_wait: Option<Duration>) -> (Arc<StubBackend>, Queue) {
+        let backend = StubBackend::gated(0);
+        let queue = Queue::new(backend.clone(), 1, 1, 1, Duration::ZERO, admission(policy, max_wait));
+        (backend, queue)
+    }
+
+    #[tokio::test]
+    async fn merges_jobs_and_splits_results_per_job() {
+        let backend = StubBackend::new();
+        let queue = Queue::new(
+            backend.clone(),
+            1,
+            16,
+            16,
+            Duration::from_millis(100),
+            admission(QueuePolicy::Wait, None),
+        );
+
+        let normalized = embed(&queue, &["a", "bb"], true);
+        let raw = embed(&queue, &["ccc"], false);
+        let normalized = normalized.await.unwrap().unwrap();
+        let raw = raw.await.unwrap().unwrap();
+
+        // 两个任务合并为一次后端调用
+        assert_eq!(backend.calls(), vec![3]);
+
+        assert_eq!(normalized.count, 2);
+        assert_eq!(normalized.vectors, vec![vec![0.6, 0.8], vec![0.6, 0.8]]);
+        assert_eq!(normalized.token_counts, vec![1, 1]);
+        assert_eq!(raw.count, 1);
+        assert_eq!(raw.vectors, vec![vec![9.0, 12.0]]);
+        assert_eq!(raw.truncated, vec![false]);
+    }
+
+    #[tokio::test]
+    async fn invalid_input_fails_only_the_offending_job() {
+        let backend = StubBackend::new();
+        let queue = Queue::new(
+            backend.clone(),
+            1,
+            16,
+            16,
+            Duration::from_millis(100),
+            admission(QueuePolicy::Wait, None),
+        );
+
+        let good = embed(&queue, &["ok"], false);
+        let bad = embed(&queue, &["bad"], false);
+        let good = good.await.unwrap();
+        let bad = bad.await.unwrap();
+
+        // 合并调用失败后逐个重试
+        assert_eq!(backend.calls(), vec![2, 1, 1]);
+        assert_eq!(good.unwrap().vectors, vec![vec![6.0, 8.0]]);
+        assert!(matches!(bad, Err(BackendError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn stops_collecting_at_max_batch_size() {
+        let backend = StubBackend::new();
+        let queue = Queue::new(
+            backend.clone(),
+            1,
+            16,
+            2,
+            Duration::from_millis(100),
+            admission(QueuePolicy::Wait, None),
+        );
+
+        let first = embed(&queue, &["a", "b"], false);
+        let second = embed(&queue, &["c"], false);
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+
+        // 第一个任务已凑满一批，不再等待合并
+        assert_eq!(backend.calls(), vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn reject_policy_returns_queue_full() {
+        let (backend, queue) = blocked_queue(QueuePolicy::Reject, None);
+        let running = embed(&queue, &["a"], false);
+        backend.wait_for_calls(1).await;
+        let queued = embed(&queue, &["b"], false);
+        while queue.depth() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let rejected = embed(&queue, &["c"], false).await.unwrap();
+        assert!(matches!(
+            rejected,
+            Err(BackendError::QueueFull {
+                depth: 1,
+                capacity: 1,
+                retry_after: 7
+            })
+        ));
+
+        backend.gate.add_permits(2);
+        assert!(running.await.unwrap().is_ok());
+        assert!(queued.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn wait_policy_times_out() {
+        let (backend, queue) =
+            blocked_queue(QueuePolicy::Wait, Some(Duration::from_millis(50)));
+        let running = embed(&queue, &["a"], false);
+        backend.wait_for_calls(1).await;
+        let queued = embed(&queue, &["b"], false);
+        while queue.depth() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let started = Instant::now();
+        let timed_out = embed(&queue, &["c"], false).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(matches!(
+            timed_out,
+            Err(BackendError::Overloaded { retry_after: 7, .. })
+        ));
+
+        backend.gate.add_permits(2);
+        assert!(running.await.unwrap().is_ok());
+        assert!(queued.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn wait_policy_admits_once_space_frees() {
+        let (backend, queue) = blocked_queue(QueuePolicy::Wait, None);
+        let running = embed(&queue, &["a"], false);
+        backend.wait_for_calls(1).await;
+        let queued = embed(&queue, &["b"], false);
+        while queue.depth() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let waiting = embed(&queue, &["c"], false);
+
+        backend.gate.add_permits(3);
+        assert!(running.await.unwrap().is_ok());
+        assert!(queued.await.unwrap().is_ok());
+        assert_eq!(waiting.await.unwrap().unwrap().vectors, vec![vec![3.0, 4.0]]);
+    }
+
+    #[tokio::test]
+    async fn shed_oldest_policy_drops_the_oldest_queued_job() {
+        let (backend, queue) = blocked_queue(QueuePolicy::ShedOldest, None);
+        let running = embed(&queue, &["a"], false);
+        backend.wait_for_calls(1).await;
+        let oldest = embed(&queue, &["b"], false);
+        while queue.depth() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
 
-        self.sender
-            .send(job)
-            .await
-            .map_err(|_| BackendError::Request("queue send failed".to_string()))?;
+        let newest = embed(&queue, &["cc"], false);
+        assert!(matches!(
+            oldest.await.unwrap(),
+            Err(BackendError::Overloaded { retry_after: 7, .. })
+        ));
+        assert_eq!(queue.depth(), 1);
 
-        rx.await
-            .map_err(|_| BackendError::Request("queue response dropped".to_string()))?
+        backend.gate.add_permits(2);
+        assert!(running.await.unwrap().is_ok());
+        assert_eq!(newest.await.unwrap().unwrap().vectors, vec![vec![6.0, 8.0]]);
     }
 }
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::backend::{EmbeddingBackend, ModelInfo, ModelTask};
+use crate::config::Config;
+use crate::queue::Queue;
+
+/// 一个已加载的模型及其请求队列
+pub struct ServedModel {
+    pub name: String,
+    pub queue: Queue,
+    /// 分词等不需要排队的操作直接调用后端
+    pub backend: Arc<dyn EmbeddingBackend>,
+    pub config: Config,
+    pub model_info: ModelInfo,
+    /// 加载完成的 Unix 时间戳（秒）
+    pub created: u64,
+}
+
+/// 按请求中的 model 字段（模型名或别名）查找模型
+#[derive(Default)]
+pub struct ModelRegistry {
+    models: Vec<Arc<ServedModel>>,
+    routes: HashMap<String, usize>,
+}
+
+impl ModelRegistry {
+    /// 注册模型，名称或别名与已注册的冲突时返回错误；第一个注册的模型为默认模型
+    pub fn register(&mut self, model: ServedModel, aliases: &[String]) -> Result<(), String> {
+        let index = self.models.len();
+        for name in std::iter::once(&model.name).chain(aliases) {
+            if self.routes.insert(name.clone(), index).is_some() {
+                return Err(format!("duplicate model name or alias: {}", name));
+            }
+        }
+        self.models.push(Arc::new(model));
+        Ok(())
+    }
+
+    /// 未指定 model 时返回默认模型，名称未注册时返回 None
+    pub fn get(&self, name: Option<&str>) -> Option<Arc<ServedModel>> {
+        let index = match name {
+            Some(name) => *self.routes.get(name)?,
+            None => 0,
+        };
+        self.models.get(index).cloned()
+    }
+
+    /// 第一个提供指定能力的模型（按注册顺序，默认模型优先）
+    pub fn find_task(&self, task: ModelTask) -> Option<Arc<ServedModel>> {
+        self.models
+            .iter()
+            .find(|model| model.model_info.task == task)
+            .cloned()
+    }
+
+    pub fn models(&self) -> &[Arc<ServedModel>] {
+        &self.models
+    }
+}
@@ -0,0 +1,189 @@
+//! 与 Hugging Face Text Embeddings Inference（TEI）兼容的接口
+//!
+//! TEI 的请求中没有模型名称：/rerank 使用第一个重排序模型，/embed 与 /embed_all 使用第一个嵌入模型，
+//! 没有对应能力的模型时退回默认模型（由后端返回不支持的错误）；/tokenize 与 /info 使用默认模型。
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::backend::candle::ModelDType;
+use crate::backend::ModelTask;
+use crate::types::{
+    SimpleToken, TeiEmbedAllRequest, TeiEmbedRequest, TeiInfo, TeiRank, TeiRerankRequest,
+    TeiTokenizeRequest, TeiTruncationDirection, Truncation,
+};
+use crate::registry::ServedModel;
+use crate::{
+    check_dimensions, queue_headers, truncate_dimensions, AppError, AppJson, AppState, QueueHeaders,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/embed", post(embed))
+        .route("/embed_all", post(embed_all))
+        .route("/rerank", post(rerank))
+        .route("/tokenize", post(tokenize))
+        .route("/info", get(info))
+}
+
+// 多模型时按能力选择模型，不依赖默认模型的类型
+fn model_for(state: &AppState, task: ModelTask) -> Result<Arc<ServedModel>, AppError> {
+    match state.models.find_task(task) {
+        Some(model) => Ok(model),
+        None => state.model(None),
+    }
+}
+
+// truncate 未设置时使用配置的截断方式，false 时超长报错
+fn truncation(
+    truncate: Option<bool>,
+    direction: TeiTruncationDirection,
+    default: Truncation,
+) -> Truncation {
+    match (truncate, direction) {
+        (None, _) => default,
+        (Some(false), _) => Truncation::Error,
+        (Some(true), TeiTruncationDirection::Left) => Truncation::Left,
+        (Some(true), TeiTruncationDirection::Right) => Truncation::Right,
+    }
+}
+
+async fn embed(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TeiEmbedRequest>,
+) -> Result<(QueueHeaders, Json<Vec<Vec<f32>>>), AppError> {
+    let model = model_for(&state, ModelTask::Embedding)?;
+    let inputs = payload.inputs.into_vec();
+    if inputs.is_empty() {
+        return Err(AppError::BadRequest("inputs cannot be empty".to_string()));
+    }
+    check_dimensions(model.model_info.dimension, payload.dimensions)?;
+
+    let mut response = model
+        .queue
+        .enqueue(
+            inputs,
+            payload.normalize,
+            model.config.batch_size,
+            truncation(payload.truncate, payload.truncation_direction, model.config.truncation),
+            payload.prompt_name,
+        )
+        .await?;
+
+    if let Some(dimensions) = payload.dimensions {
+        truncate_dimensions(&mut response, dimensions, payload.normalize)?;
+    }
+    Ok((queue_headers(&model.queue), Json(response.vectors)))
+}
+
+async fn embed_all(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TeiEmbedAllRequest>,
+) -> Result<(QueueHeaders, Json<Vec<Vec<Vec<f32>>>>), AppError> {
+    let model = model_for(&state, ModelTask::Embedding)?;
+    let inputs = payload.inputs.into_vec();
+    if inputs.is_empty() {
+        return Err(AppError::BadRequest("inputs cannot be empty".to_string()));
+    }
+
+    let embeddings = model
+        .queue
+        .enqueue_embed_all(
+            inputs,
+            model.config.batch_size,
+            truncation(payload.truncate, payload.truncation_direction, model.config.truncation),
+            payload.prompt_name,
+        )
+        .await?;
+    Ok((queue_headers(&model.queue), Json(embeddings)))
+}
+
+async fn rerank(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TeiRerankRequest>,
+) -> Result<(QueueHeaders, Json<Vec<TeiRank>>), AppError> {
+    let model = model_for(&state, ModelTask::Rerank)?;
+    if payload.texts.is_empty() {
+        return Err(AppError::BadRequest("texts cannot be empty".to_string()));
+    }
+
+    let scores = model
+        .queue
+        .enqueue_rerank(payload.query, payload.texts.clone(), model.config.batch_size)
+        .await?;
+    let values = if payload.raw_scores {
+        scores.logits
+    } else {
+        scores.scores
+    };
+
+    let mut ranks: Vec<TeiRank> = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, score)| TeiRank {
+            index,
+            score,
+            text: payload.return_text.then(|| payload.texts[index].clone()),
+        })
+        .collect();
+    ranks.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok((queue_headers(&model.queue), Json(ranks)))
+}
+
+async fn tokenize(
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TeiTokenizeRequest>,
+) -> Result<Json<Vec<Vec<SimpleToken>>>, AppError> {
+    let model = state.model(None)?;
+    let inputs = payload.inputs.into_vec();
+    if inputs.is_empty() {
+        return Err(AppError::BadRequest("inputs cannot be empty".to_string()));
+    }
+
+    let tokenized = model.backend.tokenize(
+        inputs,
+        payload.add_special_tokens,
+        payload.prompt_name.as_deref(),
+    )?;
+    let tokens = tokenized
+        .into_iter()
+        .map(|t| t.tokens.into_iter().map(SimpleToken::from).collect())
+        .collect();
+    Ok(Json(tokens))
+}
+
+async fn info(State(state): State<AppState>) -> Result<Json<TeiInfo>, AppError> {
+    let model = state.model(None)?;
+    let model_info = &model.model_info;
+    let model_type = match model_info.task {
+        ModelTask::Embedding => json!({ "embedding": { "pooling": model_info.pooling } }),
+        ModelTask::Rerank => json!({ "reranker": {} }),
+    };
+    // GGUF 模型报告量化类型
+    let model_dtype = model_info.quantization.clone().unwrap_or_else(|| {
+        match model.config.dtype {
+            ModelDType::F32 => "float32",
+            ModelDType::F16 => "float16",
+            ModelDType::Bf16 => "bfloat16",
+        }
+        .to_string()
+    });
+
+    Ok(Json(TeiInfo {
+        model_id: model.name.clone(),
+        model_sha: None,
+        model_dtype,
+        model_type,
+        max_concurrent_requests: model.config.queue_capacity,
+        max_input_length: model_info.max_seq_len,
+        max_batch_tokens: model.config.max_batch_tokens,
+        max_client_batch_size: model.config.batch_size,
+        tokenization_workers: model.config.compute_threads,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }))
+}
@@ -1,17 +1,68 @@
 use serde::{Deserialize, Serialize};
 
+use crate::backend::ModelInfo;
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum InputText {
     Single(String),
     Multiple(Vec<String>),
+    Tokens(Vec<u32>),
+    TokenBatch(Vec<Vec<u32>>),
 }
 
 impl InputText {
-    pub fn into_vec(self) -> Vec<String> {
+    pub fn into_vec(self) -> Vec<EmbedInput> {
         match self {
-            InputText::Single(s) => vec![s],
-            InputText::Multiple(v) => v,
+            InputText::Single(s) => vec![EmbedInput::Text(s)],
+            InputText::Multiple(v) => v.into_iter().map(EmbedInput::Text).collect(),
+            InputText::Tokens(ids) => vec![EmbedInput::Tokens(ids)],
+            InputText::TokenBatch(v) => v.into_iter().map(EmbedInput::Tokens).collect(),
+        }
+    }
+}
+
+/// 单条待编码的输入：原始文本，或已经分好词的 token id（按原样送入模型，不再添加特殊 token）
+#[derive(Debug, Clone)]
+pub enum EmbedInput {
+    Text(String),
+    Tokens(Vec<u32>),
+}
+
+impl From<String> for EmbedInput {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+/// 输入超过模型最大长度时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    /// 直接返回错误
+    Error,
+    /// 丢弃开头的 token，保留末尾
+    Left,
+    /// 丢弃末尾的 token，保留开头
+    #[default]
+    Right,
+    /// 切分为有重叠的窗口，各窗口向量取平均
+    ChunkMean,
+    /// 切分为有重叠的窗口，各窗口向量按 token 数加权平均
+    ChunkWeightedMean,
+}
+
+impl std::str::FromStr for Truncation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "chunk_mean" => Ok(Self::ChunkMean),
+            "chunk_weighted_mean" => Ok(Self::ChunkWeightedMean),
+            _ => Err(format!("Invalid truncation: {}", s)),
         }
     }
 }
@@ -21,6 +72,14 @@ pub struct OpenAIEmbeddingsRequest {
     pub input: InputText,
     pub model: Option<String>,
     pub encoding_format: Option<String>,
+    /// 只保留向量的前 dimensions 维（Matryoshka 表示）
+    pub dimensions: Option<usize>,
+    /// 超长输入的处理方式，未设置时使用配置
+    pub truncation: Option<Truncation>,
+    /// 文本前缀使用的提示词名称，如 "query"、"passage"
+    pub prompt_name: Option<String>,
+    /// 同 prompt_name，两者都设置时以 prompt_name 为准
+    pub input_type: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -29,26 +88,131 @@ pub struct OpenAIEmbeddingsResponse {
     pub data: Vec<EmbeddingData>,
     pub model: String,
     pub usage: Usage,
+    /// 是否有输入因超长被截断
+    pub truncated: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct EmbeddingData {
     pub object: String,
-    pub embedding: Vec<f32>,
+    pub embedding: Embedding,
     pub index: usize,
 }
 
+/// encoding_format=float 时为浮点数组，base64 时为小端 f32 字节的 base64 字符串
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Embedding {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
 #[derive(Debug, Serialize)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub total_tokens: u32,
 }
 
+/// /v1/models 中的一个模型，在 OpenAI 的字段之外附带模型元数据
+#[derive(Debug, Serialize)]
+pub struct ModelObject {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub owned_by: String,
+    pub backend_type: String,
+    pub aliases: Vec<String>,
+    #[serde(flatten)]
+    pub info: ModelInfo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+    pub object: String,
+    pub data: Vec<ModelObject>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EmbedRequest {
     pub texts: InputText,
+    /// 目标模型名称或别名，未设置时使用默认模型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
     pub normalize_embeddings: bool,
     pub batch_size: u32,
+    /// 超长输入的处理方式，未设置时使用配置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<Truncation>,
+    /// 文本前缀使用的提示词名称，如 "query"、"passage"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_name: Option<String>,
+    /// 同 prompt_name，两者都设置时以 prompt_name 为准
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_type: Option<String>,
+}
+
+/// 后端返回的重排序结果，与文档一一对应
+#[derive(Debug, Clone)]
+pub struct RerankScores {
+    /// 归一化到 0~1 的相关性分数
+    pub scores: Vec<f32>,
+    /// 分类头输出的原始 logit（多标签模型为最后一个标签的 logit）
+    pub logits: Vec<f32>,
+    /// 每个 query + 文档句对的 token 数
+    pub token_counts: Vec<usize>,
+}
+
+/// 文档可以是字符串，也可以是 {"text": ...} 对象
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RerankDocument {
+    Text(String),
+    Object { text: String },
+}
+
+impl RerankDocument {
+    pub fn into_text(self) -> String {
+        match self {
+            RerankDocument::Text(text) | RerankDocument::Object { text } => text,
+        }
+    }
+}
+
+/// 兼容 Cohere / Jina 的重排序请求
+#[derive(Debug, Deserialize)]
+pub struct RerankRequest {
+    pub model: Option<String>,
+    pub query: String,
+    pub documents: Vec<RerankDocument>,
+    /// 只返回分数最高的 top_n 个，未设置时返回全部
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    pub return_documents: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankResponse {
+    pub model: String,
+    pub results: Vec<RerankResult>,
+    pub usage: RerankUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankResult {
+    pub index: usize,
+    pub relevance_score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<RerankResultDocument>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankResultDocument {
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankUsage {
+    pub total_tokens: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -57,4 +221,229 @@ pub struct EmbedResponse {
     pub count: usize,
     pub vector_dim: usize,
     pub model_path: String,
+    /// 每条输入的 token 数，后端无法统计时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_counts: Vec<usize>,
+    /// 每条输入是否因超长被截断（分块处理不算截断），后端未返回时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated: Vec<bool>,
+}
+
+/// 后端返回的一条文本的分词结果
+#[derive(Debug, Clone)]
+pub struct Tokenized {
+    pub tokens: Vec<TokenInfo>,
+    /// 加上特殊 token 后超出模型最大长度、嵌入时会被截断的 token 数，最大长度未知时为 None
+    pub truncated_tokens: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    pub id: u32,
+    /// 词表中的 token 字符串，如 "##ing"
+    pub token: String,
+    /// 该 token 在输入中对应的片段，特殊 token 为 token 字符串本身
+    pub text: String,
+    pub special: bool,
+    /// 是否属于提示词或指令前缀
+    pub prefix: bool,
+    /// 在原始输入中的字符偏移 [start, stop)，特殊 token 与前缀 token 为 None
+    pub offsets: Option<(usize, usize)>,
+}
+
+/// 分词结果中的一个 token，与 TEI 的 /tokenize 一致
+///
+/// text 为该 token 在输入中对应的片段（特殊 token 为其本身），start / stop 为原始输入中的字符偏移，
+/// 特殊 token 与提示词前缀的 token 为 None。
+#[derive(Debug, Clone, Serialize)]
+pub struct SimpleToken {
+    pub id: u32,
+    pub text: String,
+    pub special: bool,
+    pub start: Option<usize>,
+    pub stop: Option<usize>,
+}
+
+impl From<TokenInfo> for SimpleToken {
+    fn from(token: TokenInfo) -> Self {
+        Self {
+            id: token.id,
+            text: token.text,
+            special: token.special,
+            start: token.offsets.map(|(start, _)| start),
+            stop: token.offsets.map(|(_, stop)| stop),
+        }
+    }
+}
+
+/// TEI 的截断方向
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum TeiTruncationDirection {
+    #[serde(alias = "left")]
+    Left,
+    #[default]
+    #[serde(alias = "right")]
+    Right,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// TEI 的 /embed 请求
+#[derive(Debug, Deserialize)]
+pub struct TeiEmbedRequest {
+    pub inputs: InputText,
+    #[serde(default = "default_true")]
+    pub normalize: bool,
+    /// true 时按 truncation_direction 截断，false 时超长报错，未设置时使用配置
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    pub truncation_direction: TeiTruncationDirection,
+    pub prompt_name: Option<String>,
+    pub dimensions: Option<usize>,
+}
+
+/// TEI 的 /embed_all 请求，返回每个 token 的隐藏状态
+#[derive(Debug, Deserialize)]
+pub struct TeiEmbedAllRequest {
+    pub inputs: InputText,
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    pub truncation_direction: TeiTruncationDirection,
+    pub prompt_name: Option<String>,
+}
+
+/// TEI 的 /rerank 请求
+#[derive(Debug, Deserialize)]
+pub struct TeiRerankRequest {
+    pub query: String,
+    pub texts: Vec<String>,
+    /// 返回分类头的原始 logit 而不是 sigmoid 后的分数
+    #[serde(default)]
+    pub raw_scores: bool,
+    #[serde(default)]
+    pub return_text: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeiRank {
+    pub index: usize,
+    pub score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// 分词接口的输入：单条或多条文本
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TokenizeInput {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl TokenizeInput {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            TokenizeInput::Single(text) => vec![text],
+            TokenizeInput::Multiple(texts) => texts,
+        }
+    }
+}
+
+/// TEI 的 /tokenize 请求
+#[derive(Debug, Deserialize)]
+pub struct TeiTokenizeRequest {
+    pub inputs: TokenizeInput,
+    #[serde(default = "default_true")]
+    pub add_special_tokens: bool,
+    pub prompt_name: Option<String>,
+}
+
+/// /tokenize 请求
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    pub input: TokenizeInput,
+    pub model: Option<String>,
+    #[serde(default = "default_true")]
+    pub add_special_tokens: bool,
+    /// 与嵌入接口相同，文本先加上提示词前缀再分词
+    pub prompt_name: Option<String>,
+    pub input_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenizeResponse {
+    pub model: String,
+    /// 单条输入允许的最大 token 数（含特殊 token），未知时为 null
+    pub max_seq_len: Option<usize>,
+    pub data: Vec<TokenizeData>,
+}
+
+/// 一条输入的分词结果，各数组与 token 一一对应
+#[derive(Debug, Serialize)]
+pub struct TokenizeData {
+    pub index: usize,
+    pub ids: Vec<u32>,
+    pub tokens: Vec<String>,
+    /// 在原始输入中的字符偏移 [start, stop)，特殊 token 与前缀 token 为 null
+    pub offsets: Vec<Option<(usize, usize)>>,
+    pub special: Vec<bool>,
+    /// 是否属于提示词前缀
+    pub prefix: Vec<bool>,
+    pub count: usize,
+    pub truncated_tokens: Option<usize>,
+}
+
+/// 单条或多条 token id 序列
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TokenIdsInput {
+    Single(Vec<u32>),
+    Multiple(Vec<Vec<u32>>),
+}
+
+impl TokenIdsInput {
+    pub fn into_vec(self) -> Vec<Vec<u32>> {
+        match self {
+            TokenIdsInput::Single(ids) => vec![ids],
+            TokenIdsInput::Multiple(ids) => ids,
+        }
+    }
+}
+
+/// /detokenize 请求
+#[derive(Debug, Deserialize)]
+pub struct DetokenizeRequest {
+    pub input: TokenIdsInput,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub skip_special_tokens: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DetokenizeResponse {
+    pub model: String,
+    pub data: Vec<DetokenizeData>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DetokenizeData {
+    pub index: usize,
+    pub text: String,
+}
+
+/// TEI 的 /info 响应
+#[derive(Debug, Serialize)]
+pub struct TeiInfo {
+    pub model_id: String,
+    pub model_sha: Option<String>,
+    pub model_dtype: String,
+    pub model_type: serde_json::Value,
+    pub max_concurrent_requests: usize,
+    pub max_input_length: Option<usize>,
+    pub max_batch_tokens: usize,
+    pub max_client_batch_size: u32,
+    pub tokenization_workers: usize,
+    pub version: String,
 }